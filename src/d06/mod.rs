@@ -1,9 +1,9 @@
 use anyhow::{Context, Result};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Race {
-    time: usize,
-    distance_to_beat: usize,
+pub struct Race {
+    pub time: u128,
+    pub distance_to_beat: u128,
 }
 
 // for part 2
@@ -19,15 +19,18 @@ impl TryFrom<&str> for Race {
             .filter(|t| !t.is_empty())
             .collect::<Vec<&str>>()
             .join("")
-            .parse::<usize>()?;
+            .parse::<u128>()?;
         let distance_to_beat = distance_to_beat
             .split(" ")
             .filter(|t| !t.is_empty())
             .collect::<Vec<&str>>()
             .join("")
-            .parse::<usize>()?;
+            .parse::<u128>()?;
 
-        Ok(Self { time, distance_to_beat })
+        Ok(Self {
+            time,
+            distance_to_beat,
+        })
     }
 }
 
@@ -49,11 +52,11 @@ impl TryFrom<&str> for RaceBoard {
         let mut distances = distances.split(" ").filter(|t| !t.is_empty());
 
         for time in times {
-            let time = time.parse::<usize>().context("Bad input")?;
+            let time = time.parse::<u128>().context("Bad input")?;
             let distance_to_beat = distances
                 .next()
                 .context("Bad input")?
-                .parse::<usize>()
+                .parse::<u128>()
                 .context("Bad input")?;
 
             races.push(Race {
@@ -66,58 +69,249 @@ impl TryFrom<&str> for RaceBoard {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Boat {
-    speed: usize,
+// 256-bit unsigned integer, just enough to hold the square of a race time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Wide {
+    hi: u128,
+    lo: u128,
 }
 
-impl Boat {
-    fn calculate_distance(&self, race_time: usize, charge_time: usize) -> usize {
-        (charge_time * self.speed) * (race_time - charge_time)
+impl Wide {
+    fn from_u128(value: u128) -> Self {
+        Self { hi: 0, lo: value }
     }
 
-    fn will_win(&self, race: &Race, charge_time: usize) -> bool {
-        self.calculate_distance(race.time, charge_time) > race.distance_to_beat
+    fn mul(a: u128, b: u128) -> Self {
+        let mask = u64::MAX as u128;
+        let (a_hi, a_lo) = (a >> 64, a & mask);
+        let (b_hi, b_lo) = (b >> 64, b & mask);
+
+        let lo_lo = a_lo * b_lo;
+        let hi_lo = a_hi * b_lo;
+        let lo_hi = a_lo * b_hi;
+        let hi_hi = a_hi * b_hi;
+
+        let cross = (lo_lo >> 64) + (hi_lo & mask) + (lo_hi & mask);
+        let lo = (cross << 64) | (lo_lo & mask);
+        let hi = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (cross >> 64);
+
+        Self { hi, lo }
     }
 
-    fn how_many_diff_ways_to_win(&self, race: &Race) -> usize {
-        let mut left_margin = 0;
-        let mut right_margin = race.time;
-        let min_to_win = loop {
-            let guess = (left_margin + right_margin) / 2;
-            if guess == left_margin {
-                if self.will_win(&race, left_margin) {
-                    break left_margin;
-                } else {
-                    break right_margin;
-                }
-            }
-            if self.will_win(&race, guess) {
-                right_margin = guess;
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        if self < other {
+            return None;
+        }
+        let (lo, borrow) = self.lo.overflowing_sub(other.lo);
+        let hi = self.hi - other.hi - borrow as u128;
+        Some(Self { hi, lo })
+    }
+
+    fn halve(self) -> Self {
+        Self {
+            hi: self.hi >> 1,
+            lo: (self.lo >> 1) | (self.hi << 127),
+        }
+    }
+
+    fn to_u128(self) -> Option<u128> {
+        match self.hi {
+            0 => Some(self.lo),
+            _ => None,
+        }
+    }
+
+    // largest r such that r * r <= self
+    fn isqrt(self) -> u128 {
+        let mut low = 0u128;
+        let mut high = u128::MAX;
+        while low < high {
+            let guess = high - (high - low) / 2;
+            if Wide::mul(guess, guess) <= self {
+                low = guess;
             } else {
-                left_margin = guess;
+                high = guess - 1;
             }
+        }
+        low
+    }
+}
+
+pub trait BoatModel {
+    // Distance travelled when charging for charge_time out of race_time, or None if it
+    // does not fit in a u128. It must rise strictly up to its peak and never rise again after it.
+    fn calculate_distance(&self, race_time: u128, charge_time: u128) -> Option<u128>;
+
+    fn will_win(&self, race: &Race, charge_time: u128) -> bool {
+        match self.calculate_distance(race.time, charge_time) {
+            Some(distance) => distance > race.distance_to_beat,
+            None => true,
+        }
+    }
+
+    fn how_many_diff_ways_to_win(&self, race: &Race) -> u128 {
+        how_many_diff_ways_to_win_by_search(self, race)
+    }
+}
+
+fn how_many_diff_ways_to_win_by_search<B: BoatModel + ?Sized>(boat: &B, race: &Race) -> u128 {
+    // distances too large for a u128 sort after every other distance
+    let distance = |charge_time| match boat.calculate_distance(race.time, charge_time) {
+        Some(distance) => (false, distance),
+        None => (true, 0),
+    };
+
+    // first charge time after which the distance stops increasing
+    let mut left_margin = 0;
+    let mut right_margin = race.time;
+    while left_margin < right_margin {
+        let guess = left_margin + (right_margin - left_margin) / 2;
+        if distance(guess) < distance(guess + 1) {
+            left_margin = guess + 1;
+        } else {
+            right_margin = guess;
+        }
+    }
+    let peak = left_margin;
+
+    if !boat.will_win(race, peak) {
+        return 0;
+    }
+
+    let mut left_margin = 0;
+    let mut right_margin = peak;
+    while left_margin < right_margin {
+        let guess = left_margin + (right_margin - left_margin) / 2;
+        if boat.will_win(race, guess) {
+            right_margin = guess;
+        } else {
+            left_margin = guess + 1;
+        }
+    }
+    let min_to_win = left_margin;
+
+    let mut left_margin = peak;
+    let mut right_margin = race.time;
+    while left_margin < right_margin {
+        let guess = right_margin - (right_margin - left_margin) / 2;
+        if boat.will_win(race, guess) {
+            left_margin = guess;
+        } else {
+            right_margin = guess - 1;
+        }
+    }
+    let max_to_win = left_margin;
+
+    max_to_win - min_to_win + 1
+}
+
+// Speed grows linearly with the charge time, as in the puzzle
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Boat {
+    pub speed: u128,
+}
+
+impl BoatModel for Boat {
+    fn calculate_distance(&self, race_time: u128, charge_time: u128) -> Option<u128> {
+        let Some(moving_time) = race_time.checked_sub(charge_time) else {
+            return Some(0);
         };
+        charge_time
+            .checked_mul(self.speed)?
+            .checked_mul(moving_time)
+    }
 
-        let mut left_margin = 0;
-        let mut right_margin = race.time;
-        let max_to_win = loop {
-            let guess = (left_margin + right_margin) / 2;
-            if guess == left_margin {
-                if self.will_win(&race, right_margin) {
-                    break right_margin;
-                } else {
-                    break left_margin;
-                }
-            }
-            if self.will_win(&race, guess) {
-                left_margin = guess;
-            } else {
-                right_margin = guess;
-            }
+    fn how_many_diff_ways_to_win(&self, race: &Race) -> u128 {
+        if self.speed == 0 {
+            return 0;
+        }
+
+        // speed * c * (T - c) > D is the same as c * (T - c) > D / speed, since the
+        // left side is an integer. Substituting x = T - 2c turns it into x^2 < T^2 - 4 * (D / speed)
+        let threshold = Wide::mul(race.distance_to_beat / self.speed, 4);
+        let discriminant = match Wide::mul(race.time, race.time).checked_sub(threshold) {
+            Some(d) if d > Wide::from_u128(0) => d,
+            _ => return 0,
+        };
+        let max_x = discriminant
+            .checked_sub(Wide::from_u128(1))
+            .unwrap_or(Wide::from_u128(0))
+            .isqrt();
+
+        // x takes the values T, T - 2, ..., -T, so it has the same parity as T
+        if race.time.is_multiple_of(2) {
+            2 * (max_x / 2) + 1
+        } else {
+            2 * max_x.div_ceil(2)
+        }
+    }
+}
+
+// Like Boat, but the speed stops growing once it reaches max_speed
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CappedBoat {
+    pub speed: u128,
+    pub max_speed: u128,
+}
+
+impl BoatModel for CappedBoat {
+    fn calculate_distance(&self, race_time: u128, charge_time: u128) -> Option<u128> {
+        let Some(moving_time) = race_time.checked_sub(charge_time) else {
+            return Some(0);
+        };
+        charge_time
+            .saturating_mul(self.speed)
+            .min(self.max_speed)
+            .checked_mul(moving_time)
+    }
+}
+
+// Speed grows by acceleration for each ms of charge, and drag slows the boat down
+// by a fixed amount for each ms it moves, until it stops
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DraggedBoat {
+    pub acceleration: u128,
+    pub drag: u128,
+}
+
+impl BoatModel for DraggedBoat {
+    fn calculate_distance(&self, race_time: u128, charge_time: u128) -> Option<u128> {
+        let Some(moving_time) = race_time.checked_sub(charge_time) else {
+            return Some(0);
+        };
+        let speed = charge_time.checked_mul(self.acceleration)?;
+        let moving_time = match self.drag {
+            0 => moving_time,
+            drag => moving_time.min(speed.div_ceil(drag)),
         };
+        if moving_time == 0 {
+            return Some(0);
+        }
+
+        // speed + (speed - drag) + ... + (speed - (moving_time - 1) * drag), where the
+        // last term is never negative, so (moving_time - 1) * drag fits in a u128
+        let slowdown = Wide::mul(self.drag * (moving_time - 1), moving_time).halve();
+        Wide::mul(speed, moving_time)
+            .checked_sub(slowdown)?
+            .to_u128()
+    }
+}
+
+// Like Boat, but releasing the button stalls the boat for penalty ms
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PenalizedBoat {
+    pub speed: u128,
+    pub penalty: u128,
+}
 
-        max_to_win - min_to_win + 1
+impl BoatModel for PenalizedBoat {
+    fn calculate_distance(&self, race_time: u128, charge_time: u128) -> Option<u128> {
+        let Some(moving_time) = race_time.checked_sub(charge_time) else {
+            return Some(0);
+        };
+        charge_time
+            .checked_mul(self.speed)?
+            .checked_mul(moving_time.saturating_sub(self.penalty))
     }
 }
 
@@ -126,13 +320,15 @@ pub fn run_part_1(input: String) -> Result<usize> {
 
     let race_board = RaceBoard::try_from(input.trim())?;
 
-    let mut result = 1;
+    let mut result: u128 = 1;
 
     for race in race_board.races.iter() {
-        result *= boat.how_many_diff_ways_to_win(&race);
+        result = result
+            .checked_mul(boat.how_many_diff_ways_to_win(race))
+            .context("Result too large")?;
     }
 
-    Ok(result)
+    usize::try_from(result).context("Result too large")
 }
 
 pub fn run_part_2(input: String) -> Result<usize> {
@@ -140,13 +336,21 @@ pub fn run_part_2(input: String) -> Result<usize> {
 
     let race = Race::try_from(input.trim())?;
 
-    Ok(boat.how_many_diff_ways_to_win(&race))
+    usize::try_from(boat.how_many_diff_ways_to_win(&race)).context("Result too large")
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::d06::how_many_diff_ways_to_win_by_search;
     use crate::d06::run_part_1;
     use crate::d06::run_part_2;
+    use crate::d06::Boat;
+    use crate::d06::BoatModel;
+    use crate::d06::CappedBoat;
+    use crate::d06::DraggedBoat;
+    use crate::d06::PenalizedBoat;
+    use crate::d06::Race;
+    use crate::test_util::Lcg;
     use std::fs::read_to_string;
 
     #[test]
@@ -172,4 +376,101 @@ mod tests {
         let input = read_to_string("src/d06/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 40087680);
     }
+
+    #[test]
+    fn closed_form_matches_search() {
+        let mut lcg = Lcg::new(0x2023_0006);
+        let mut next = |max: u64| lcg.next_u64() % max;
+
+        for _ in 0..10000 {
+            let boat = Boat {
+                speed: next(5) as u128 + 1,
+            };
+            let time = next(1000) as u128;
+            let max_distance = boat.speed * (time / 2) * (time - time / 2);
+            let race = Race {
+                time,
+                distance_to_beat: next(max_distance as u64 + 1) as u128,
+            };
+            assert_eq!(
+                boat.how_many_diff_ways_to_win(&race),
+                how_many_diff_ways_to_win_by_search(&boat, &race),
+                "{:?} {:?}",
+                boat,
+                race
+            );
+        }
+    }
+
+    #[test]
+    fn closed_form_huge_race() {
+        let boat = Boat { speed: 1 };
+        let race = Race {
+            time: u128::MAX,
+            distance_to_beat: u128::MAX,
+        };
+        assert_eq!(
+            boat.how_many_diff_ways_to_win(&race),
+            how_many_diff_ways_to_win_by_search(&boat, &race)
+        );
+    }
+
+    #[test]
+    fn other_models_match_brute_force() {
+        let models: Vec<Box<dyn BoatModel>> = vec![
+            Box::new(CappedBoat {
+                speed: 3,
+                max_speed: 40,
+            }),
+            Box::new(DraggedBoat {
+                acceleration: 2,
+                drag: 3,
+            }),
+            Box::new(DraggedBoat {
+                acceleration: 5,
+                drag: 1,
+            }),
+            Box::new(PenalizedBoat {
+                speed: 2,
+                penalty: 7,
+            }),
+        ];
+
+        for boat in models.iter() {
+            for time in 0..60 {
+                for distance_to_beat in (0..1200).step_by(7) {
+                    let race = Race {
+                        time,
+                        distance_to_beat,
+                    };
+                    let expected = (0..=time).filter(|c| boat.will_win(&race, *c)).count();
+                    assert_eq!(boat.how_many_diff_ways_to_win(&race), expected as u128);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn charging_past_race_time() {
+        let models: Vec<Box<dyn BoatModel>> = vec![
+            Box::new(Boat { speed: 2 }),
+            Box::new(CappedBoat {
+                speed: 3,
+                max_speed: 40,
+            }),
+            Box::new(DraggedBoat {
+                acceleration: 2,
+                drag: 3,
+            }),
+            Box::new(PenalizedBoat {
+                speed: 2,
+                penalty: 7,
+            }),
+        ];
+
+        for boat in models.iter() {
+            assert_eq!(boat.calculate_distance(10, 11), Some(0));
+            assert_eq!(boat.calculate_distance(0, u128::MAX), Some(0));
+        }
+    }
 }
@@ -1,160 +1,269 @@
-use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::fmt::Display;
 
 use anyhow::{Context, Result};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Card {
-    Joker,
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
-    Eight,
-    Nine,
-    Ten,
-    Jack,
-    Queen,
-    King,
-    Ace,
+struct Card {
+    label: char,
+    suit: Option<char>,
 }
 
-impl TryFrom<char> for Card {
-    type Error = anyhow::Error;
-
-    fn try_from(value: char) -> Result<Self> {
-        match value {
-            '2' => Ok(Card::Two),
-            '3' => Ok(Card::Three),
-            '4' => Ok(Card::Four),
-            '5' => Ok(Card::Five),
-            '6' => Ok(Card::Six),
-            '7' => Ok(Card::Seven),
-            '8' => Ok(Card::Eight),
-            '9' => Ok(Card::Nine),
-            'T' => Ok(Card::Ten),
-            'J' => Ok(Card::Jack),
-            'Q' => Ok(Card::Queen),
-            'K' => Ok(Card::King),
-            'A' => Ok(Card::Ace),
-            _ => Err(anyhow::anyhow!("Invalid card")),
+impl Display for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.suit {
+            Some(suit) => write!(f, "{}{}", self.label, suit),
+            None => write!(f, "{}", self.label),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum HandKind {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandKind {
     HighCard,
     OnePair,
     TwoPair,
     ThreeOfAKind,
+    Straight,
+    Flush,
     FullHouse,
     FourOfAKind,
+    StraightFlush,
     FiveOfAKind,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Ord)]
-struct Hand {
-    cards: Vec<Card>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TieBreak {
+    // compare cards in the order they were dealt, as in the puzzle
+    Lexicographic,
+    // compare cards from the strongest to the weakest
+    Sorted,
 }
 
-impl TryFrom<&str> for Hand {
-    type Error = anyhow::Error;
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Ruleset {
+    // card labels from the weakest to the strongest
+    pub ranking: Vec<char>,
+    // labels that stand in for whatever card makes the best hand
+    pub wildcards: Vec<char>,
+    // sequence in which any five consecutive labels make a straight, None disables straights
+    pub straights: Option<Vec<char>>,
+    // whether five cards of the same suit make a flush, needs suited hands like "AhKhQhJhTh"
+    pub flushes: bool,
+    pub tie_break: TieBreak,
+}
 
-    fn try_from(value: &str) -> Result<Self> {
-        let cards = value
-            .chars()
-            .map(|c| Card::try_from(c))
-            .collect::<Result<Vec<Card>>>()
-            .context("At least one card is invalid")?;
+impl Ruleset {
+    pub fn standard() -> Self {
+        Self {
+            ranking: "23456789TJQKA".chars().collect(),
+            wildcards: Vec::new(),
+            straights: None,
+            flushes: false,
+            tie_break: TieBreak::Lexicographic,
+        }
+    }
 
-        if cards.len() != 5 {
-            return Err(anyhow::anyhow!("Hands need 5 cards exactly"));
+    pub fn jokers() -> Self {
+        Self {
+            ranking: "J23456789TQKA".chars().collect(),
+            wildcards: vec!['J'],
+            ..Self::standard()
         }
+    }
 
-        Ok(Self { cards })
+    fn strength(&self, label: &char) -> usize {
+        self.ranking.iter().position(|l| l == label).unwrap_or(0)
     }
-}
 
-impl Hand {
-    fn count_cards(&self) -> BTreeMap<Card, usize> {
+    fn is_wildcard(&self, card: &Card) -> bool {
+        self.wildcards.contains(&card.label)
+    }
+
+    fn count_cards(&self, cards: &[Card]) -> BTreeMap<char, usize> {
         let mut result = BTreeMap::new();
-        for k in self.cards.iter() {
-            result.entry(k.clone()).and_modify(|v| *v += 1).or_insert(1);
+        for k in cards.iter().filter(|c| !self.is_wildcard(c)) {
+            result.entry(k.label).and_modify(|v| *v += 1).or_insert(1);
         }
         result
     }
 
-    fn get_kind(&self) -> HandKind {
-        let mut values = self
-            .count_cards()
+    // the strongest window of the straight sequence the cards fit in
+    fn find_straight(&self, cards: &[Card], counts: &BTreeMap<char, usize>) -> Option<Vec<char>> {
+        let sequence = self.straights.as_ref()?;
+        if counts.values().any(|v| v > &1) {
+            return None;
+        }
+
+        // wildcards fill the gaps of any window holding all the other labels
+        sequence
+            .windows(cards.len())
+            .rev()
+            .find(|window| counts.keys().all(|label| window.contains(label)))
+            .map(|window| window.to_vec())
+    }
+
+    // the suit shared by every card that is not a wildcard
+    fn find_flush(&self, cards: &[Card]) -> Option<Option<char>> {
+        if !self.flushes {
+            return None;
+        }
+
+        let mut suits = cards
             .iter()
-            .filter(|(v, _)| v != &&Card::Joker)
-            .map(|(_, k)| k.clone())
-            .collect::<Vec<usize>>();
+            .filter(|c| !self.is_wildcard(c))
+            .map(|c| c.suit);
 
-        values.sort_by(|a, b| b.partial_cmp(&a).unwrap());
-
-        match values[..] {
-            // Five jokers
-            [] => HandKind::FiveOfAKind,
-            // Four jokers
-            [1] => HandKind::FiveOfAKind,
-            // Three jokers
-            [1, 1] => HandKind::FourOfAKind,
-            [2] => HandKind::FiveOfAKind,
-            // Two jokers
-            [1, 1, 1] => HandKind::ThreeOfAKind,
-            [2, 1] => HandKind::FourOfAKind,
-            [3] => HandKind::FiveOfAKind,
-            // One joker
-            [1, 1, 1, 1] => HandKind::OnePair,
-            [2, 1, 1] => HandKind::ThreeOfAKind,
-            [2, 2] => HandKind::FullHouse,
-            [3, 1] => HandKind::FourOfAKind,
-            [4] => HandKind::FiveOfAKind,
-            // No jokers
-            [1, 1, 1, 1, 1] => HandKind::HighCard,
-            [2, 1, 1, 1] => HandKind::OnePair,
-            [2, 2, 1] => HandKind::TwoPair,
-            [3, 1, 1] => HandKind::ThreeOfAKind,
-            [3, 2] => HandKind::FullHouse,
-            [4, 1] => HandKind::FourOfAKind,
+        match suits.next() {
+            None => Some(None),
+            Some(None) => None,
+            Some(suit) => suits.all(|s| s == suit).then_some(suit),
+        }
+    }
+
+    // the best kind the cards make, and the cards the wildcards turn into to make it
+    fn resolve(&self, cards: &[Card]) -> (HandKind, Vec<Card>) {
+        let counts = self.count_cards(cards);
+        let wildcards = cards.len() - counts.values().sum::<usize>();
+
+        let mut values = counts.values().cloned().collect::<Vec<usize>>();
+        values.sort_by(|a, b| b.cmp(a));
+
+        // wildcards always do best by joining the largest group
+        match values.first_mut() {
+            Some(v) => *v += wildcards,
+            None => values.push(wildcards),
+        }
+        let largest_group = counts
+            .iter()
+            .max_by_key(|(label, count)| (*count, self.strength(label)))
+            .map(|(label, _)| *label)
+            .or_else(|| {
+                self.ranking
+                    .iter()
+                    .rev()
+                    .find(|l| !self.wildcards.contains(l))
+                    .cloned()
+            });
+
+        let kind = match values[..] {
             [5] => HandKind::FiveOfAKind,
-            _ => unreachable!(),
+            [4, 1] => HandKind::FourOfAKind,
+            [3, 2] => HandKind::FullHouse,
+            [3, 1, 1] => HandKind::ThreeOfAKind,
+            [2, 2, 1] => HandKind::TwoPair,
+            [2, 1, 1, 1] => HandKind::OnePair,
+            _ => HandKind::HighCard,
+        };
+
+        let straight = self.find_straight(cards, &counts);
+        let flush = self.find_flush(cards);
+        let kind = match (&straight, &flush) {
+            (Some(_), Some(_)) => kind.max(HandKind::StraightFlush),
+            (Some(_), None) => kind.max(HandKind::Straight),
+            (None, Some(_)) => kind.max(HandKind::Flush),
+            (None, None) => kind,
+        };
+
+        let mut missing = match (&straight, kind) {
+            (Some(window), HandKind::Straight | HandKind::StraightFlush) => window
+                .iter()
+                .filter(|l| !counts.contains_key(l))
+                .cloned()
+                .collect::<Vec<char>>(),
+            _ => largest_group
+                .map(|l| vec![l; wildcards])
+                .unwrap_or_default(),
+        }
+        .into_iter();
+        let suit = match (flush, kind) {
+            (Some(Some(suit)), HandKind::Flush | HandKind::StraightFlush) => Some(suit),
+            _ => None,
+        };
+
+        let substitution = cards
+            .iter()
+            .map(|c| match self.is_wildcard(c) {
+                true => Card {
+                    label: missing.next().unwrap_or(c.label),
+                    suit: suit.or(c.suit),
+                },
+                false => c.clone(),
+            })
+            .collect::<Vec<Card>>();
+
+        (kind, substitution)
+    }
+
+    fn tie_break_strengths(&self, cards: &[Card]) -> Vec<usize> {
+        let mut strengths = cards
+            .iter()
+            .map(|c| self.strength(&c.label))
+            .collect::<Vec<usize>>();
+
+        if self.tie_break == TieBreak::Sorted {
+            strengths.sort_by(|a, b| b.cmp(a));
         }
+
+        strengths
     }
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match self.get_kind().partial_cmp(&other.get_kind()) {
-            Some(Ordering::Less) => Some(Ordering::Less),
-            Some(Ordering::Greater) => Some(Ordering::Greater),
-            Some(Ordering::Equal) => {
-                let mut k = 0;
-                loop {
-                    match self.cards[k].partial_cmp(&other.cards[k]) {
-                        Some(Ordering::Less) => {
-                            break Some(Ordering::Less);
-                        }
-                        Some(Ordering::Greater) => {
-                            break Some(Ordering::Greater);
-                        }
-                        Some(Ordering::Equal) => {
-                            k += 1;
-                            if k == 5 {
-                                break Some(Ordering::Equal);
-                            }
-                        }
-                        None => break None,
-                    }
-                }
+// Everything a hand is ranked by, computed once when the hand is parsed
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SortKey {
+    kind: HandKind,
+    strengths: Vec<usize>,
+}
+
+// The key comes first so the derived order ranks hands, and the cards
+// break the remaining ties so that Ord stays consistent with Eq
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Hand {
+    key: SortKey,
+    cards: Vec<Card>,
+    substitution: Vec<Card>,
+}
+
+impl TryFrom<(&str, &Ruleset)> for Hand {
+    type Error = anyhow::Error;
+
+    fn try_from((value, ruleset): (&str, &Ruleset)) -> Result<Self> {
+        let chars = value.chars().collect::<Vec<char>>();
+
+        // either five labels, or five label and suit pairs
+        let cards = match chars.len() {
+            5 => chars
+                .iter()
+                .map(|&label| Card { label, suit: None })
+                .collect::<Vec<Card>>(),
+            10 => chars
+                .chunks(2)
+                .map(|c| Card {
+                    label: c[0],
+                    suit: Some(c[1]),
+                })
+                .collect::<Vec<Card>>(),
+            _ => return Err(anyhow::anyhow!("Hands need 5 cards exactly")),
+        };
+
+        for card in cards.iter() {
+            if !ruleset.ranking.contains(&card.label) {
+                return Err(anyhow::anyhow!("Invalid card {}", card.label));
             }
-            None => None,
         }
+
+        let (kind, substitution) = ruleset.resolve(&cards);
+        let key = SortKey {
+            kind,
+            strengths: ruleset.tie_break_strengths(&cards),
+        };
+
+        Ok(Self {
+            key,
+            cards,
+            substitution,
+        })
     }
 }
 
@@ -164,12 +273,12 @@ struct Player {
     bid: usize,
 }
 
-impl TryFrom<&str> for Player {
+impl TryFrom<(&str, &Ruleset)> for Player {
     type Error = anyhow::Error;
 
-    fn try_from(value: &str) -> Result<Self> {
+    fn try_from((value, ruleset): (&str, &Ruleset)) -> Result<Self> {
         let (hand, bid) = value.split_once(" ").context("Bad input, no space found")?;
-        let hand = Hand::try_from(hand)?;
+        let hand = Hand::try_from((hand, ruleset))?;
         let bid = bid.parse::<usize>().context("Bid is not a number")?;
 
         Ok(Self { hand, bid })
@@ -181,73 +290,101 @@ struct Round {
     players: Vec<Player>,
 }
 
-impl TryFrom<&str> for Round {
+impl TryFrom<(&str, &Ruleset)> for Round {
     type Error = anyhow::Error;
 
-    fn try_from(value: &str) -> Result<Self> {
+    fn try_from((value, ruleset): (&str, &Ruleset)) -> Result<Self> {
         let players = value
             .split("\n")
-            .map(|player| Player::try_from(player))
+            .map(|player| Player::try_from((player, ruleset)))
             .collect::<Result<Vec<Player>>>()?;
 
         Ok(Self { players })
     }
 }
 
-impl Round {
-    fn set_jokers(mut self) -> Self {
-        self.players.iter_mut().for_each(|pl| {
-            pl.hand.cards.iter_mut().for_each(|card| {
-                if card == &mut Card::Jack {
-                    *card = Card::Joker
-                };
-            });
-        });
-
-        self
-    }
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RankedPlayer {
+    pub rank: usize,
+    pub hand: String,
+    pub kind: HandKind,
+    // the hand after the wildcards took the cards that make its kind
+    pub substitution: String,
+    pub bid: usize,
+    pub winnings: usize,
 }
 
-pub fn run_part_1(input: String) -> Result<usize> {
-    let mut result = 0;
+pub fn explain(input: &str, ruleset: &Ruleset) -> Result<impl Iterator<Item = RankedPlayer>> {
+    let mut round = Round::try_from((input.trim(), ruleset))?;
 
-    let mut round = Round::try_from(input.trim())?;
+    round.players.sort();
 
-    round
-        .players
-        .sort_by(|a, b| a.hand.partial_cmp(&b.hand).unwrap());
+    let to_string = |cards: &[Card]| cards.iter().map(|c| c.to_string()).collect::<String>();
 
-    round
+    Ok(round
         .players
-        .iter()
+        .into_iter()
         .enumerate()
-        .for_each(|(k, pl)| result += pl.bid * (k + 1));
+        .map(move |(k, pl)| RankedPlayer {
+            rank: k + 1,
+            hand: to_string(&pl.hand.cards),
+            kind: pl.hand.key.kind,
+            substitution: to_string(&pl.hand.substitution),
+            bid: pl.bid,
+            winnings: pl.bid * (k + 1),
+        }))
+}
 
-    Ok(result)
+// Quotes a field when it holds a separator, a quote or a line break, doubling its quotes
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
 }
 
-pub fn run_part_2(input: String) -> Result<usize> {
-    let mut result = 0;
+pub fn explain_csv(input: &str, ruleset: &Ruleset) -> Result<String> {
+    let mut result = String::from("rank,hand,kind,substitution,bid,winnings\n");
+
+    for pl in explain(input, ruleset)? {
+        result += &format!(
+            "{},{},{:?},{},{},{}\n",
+            pl.rank,
+            csv_field(&pl.hand),
+            pl.kind,
+            csv_field(&pl.substitution),
+            pl.bid,
+            pl.winnings
+        );
+    }
 
-    let mut round = Round::try_from(input.trim())?.set_jokers();
+    Ok(result)
+}
 
-    round
-        .players
-        .sort_by(|a, b| a.hand.partial_cmp(&b.hand).unwrap());
+pub fn total_winnings(input: &str, ruleset: &Ruleset) -> Result<usize> {
+    Ok(explain(input, ruleset)?.map(|pl| pl.winnings).sum())
+}
 
-    round
-        .players
-        .iter()
-        .enumerate()
-        .for_each(|(k, pl)| result += pl.bid * (k + 1));
+pub fn run_part_1(input: String) -> Result<usize> {
+    total_winnings(&input, &Ruleset::standard())
+}
 
-    Ok(result)
+pub fn run_part_2(input: String) -> Result<usize> {
+    total_winnings(&input, &Ruleset::jokers())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::d07::explain;
+    use crate::d07::explain_csv;
     use crate::d07::run_part_1;
     use crate::d07::run_part_2;
+    use crate::d07::total_winnings;
+    use crate::d07::Hand;
+    use crate::d07::HandKind;
+    use crate::d07::Ruleset;
+    use crate::d07::TieBreak;
     use std::fs::read_to_string;
 
     #[test]
@@ -273,4 +410,109 @@ mod tests {
         let input = read_to_string("src/d07/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 245794069);
     }
+
+    #[test]
+    fn custom_ruleset() {
+        let ruleset = Ruleset {
+            ranking: "*23456789TJQKA".chars().collect(),
+            wildcards: vec!['*'],
+            straights: Some("A23456789TJQKA".chars().collect()),
+            flushes: true,
+            tie_break: TieBreak::Sorted,
+        };
+        // pair < straight < flush < full house < straight flush
+        let input = "2h2sKdQc9c 1\nAs2d3h4c*d 10\nAhKs*cJdTh 100\n2h9hKhQh*h 1000\nKhKs*dQcQh 10000\n9h*hJh*hKh 100000";
+        assert_eq!(total_winnings(input, &ruleset).unwrap(), 654321);
+    }
+
+    #[test]
+    fn ord_is_consistent_with_ranking() {
+        let jokers = Ruleset::jokers();
+        let labels = "23456789TJQKA".chars().collect::<Vec<char>>();
+
+        let mut hands = Vec::new();
+        for n in 0..labels.len().pow(5) {
+            let value = (0..5)
+                .map(|k| labels[n / labels.len().pow(4 - k) % labels.len()])
+                .collect::<String>();
+            hands.push(Hand::try_from((value.as_str(), &jokers)).unwrap());
+        }
+        hands.sort();
+
+        // reference ranking: the best kind over every label the jokers can turn into
+        let ranking = |hand: &Hand| {
+            let mut counts = [0; 13];
+            for card in hand.cards.iter().filter(|c| c.label != 'J') {
+                counts[labels.iter().position(|l| l == &card.label).unwrap()] += 1;
+            }
+            let jokers = 5 - counts.iter().sum::<usize>();
+
+            let kind = (0..counts.len())
+                .map(|k| {
+                    let mut counts = counts;
+                    counts[k] += jokers;
+                    counts.sort_by(|a, b| b.cmp(a));
+                    match counts[..2] {
+                        [5, _] => HandKind::FiveOfAKind,
+                        [4, _] => HandKind::FourOfAKind,
+                        [3, 2] => HandKind::FullHouse,
+                        [3, _] => HandKind::ThreeOfAKind,
+                        [2, 2] => HandKind::TwoPair,
+                        [2, _] => HandKind::OnePair,
+                        _ => HandKind::HighCard,
+                    }
+                })
+                .max()
+                .unwrap();
+            (kind, hand.key.strengths.clone())
+        };
+
+        let mut previous = ranking(&hands[0]);
+        for pair in hands.windows(2) {
+            let current = ranking(&pair[1]);
+            assert!(pair[0] < pair[1]);
+            assert!(previous < current, "{:?} {:?}", pair[0], pair[1]);
+            previous = current;
+        }
+        assert_eq!(previous.0, HandKind::FiveOfAKind);
+    }
+
+    #[test]
+    fn explain_jokers() {
+        let input = read_to_string("src/d07/test.txt").expect("could not read file");
+        let players = explain(&input, &Ruleset::jokers())
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        let last = players.last().unwrap();
+        assert_eq!(last.hand, "KTJJT");
+        assert_eq!(last.kind, HandKind::FourOfAKind);
+        assert_eq!(last.substitution, "KTTTT");
+        assert_eq!(last.rank, 5);
+        assert_eq!(last.winnings, 1100);
+
+        let csv = explain_csv(&input, &Ruleset::jokers()).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("rank,hand,kind,substitution,bid,winnings")
+        );
+        assert_eq!(lines.next(), Some("1,32T3K,OnePair,32T3K,765,765"));
+
+        // labels that need quoting in a CSV field
+        let ruleset = Ruleset {
+            ranking: "\",23456789TJQKA".chars().collect(),
+            wildcards: vec!['"'],
+            straights: None,
+            flushes: false,
+            tie_break: TieBreak::Lexicographic,
+        };
+        let csv = explain_csv("2,2\"2 5\nKKKAA 7", &ruleset).unwrap();
+        let mut lines = csv.lines().skip(1);
+        assert_eq!(lines.next(), Some("1,KKKAA,FullHouse,KKKAA,7,7"));
+        assert_eq!(
+            lines.next(),
+            Some("2,\"2,2\"\"2\",FourOfAKind,\"2,222\",5,10")
+        );
+    }
 }
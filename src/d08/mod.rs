@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
 use anyhow::{Context, Result};
 
@@ -9,13 +9,94 @@ fn greatest_common_divisor(a: u128, b: u128) -> u128 {
     greatest_common_divisor(b, a % b)
 }
 
-fn least_common_multiple(nums: &[u128]) -> u128 {
-    if nums.len() == 1 {
-        return nums[0];
+fn multiply_modulo(a: u128, b: u128, modulo: u128) -> u128 {
+    if let Some(product) = a.checked_mul(b) {
+        return product % modulo;
+    }
+
+    let mut result = 0;
+    let mut a = a % modulo;
+    let mut b = b;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = add_modulo(result, a, modulo);
+        }
+        a = add_modulo(a, a, modulo);
+        b >>= 1;
+    }
+    result
+}
+
+fn add_modulo(a: u128, b: u128, modulo: u128) -> u128 {
+    match a.checked_add(b) {
+        Some(sum) => sum % modulo,
+        None => a.wrapping_add(b).wrapping_sub(modulo),
+    }
+}
+
+fn modular_inverse(a: u128, modulo: u128) -> Option<u128> {
+    let modulo = i128::try_from(modulo).ok()?;
+    let (mut old_r, mut r) = (i128::try_from(a).ok()? % modulo, modulo);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+    if old_r != 1 {
+        return None;
+    }
+    Some(old_s.rem_euclid(modulo) as u128)
+}
+
+// Steps that repeat forever, namely offset, offset + period, offset + 2 * period...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Residue {
+    offset: u128,
+    period: u128,
+}
+
+impl Residue {
+    // Generalised chinese remainder theorem: the steps both residues have in common.
+    // Ok(None) means they have none, Err means the combined period does not fit in a u128
+    fn combine(&self, other: &Residue) -> Result<Option<Residue>> {
+        let gcd = greatest_common_divisor(self.period, other.period);
+        let (a, b) = (self.offset % self.period, other.offset % other.period);
+        let difference = match b >= a {
+            true => b - a,
+            false => other.period - (a - b) % other.period,
+        };
+        if difference % gcd != 0 {
+            return Ok(None);
+        }
+
+        let period = (self.period / gcd)
+            .checked_mul(other.period)
+            .context("Combined period is too large")?;
+        let reduced = other.period / gcd;
+        let k = match reduced {
+            1 => 0,
+            _ => multiply_modulo(
+                (difference / gcd) % reduced,
+                modular_inverse(self.period / gcd, reduced).context("Period is too large")?,
+                reduced,
+            ),
+        };
+        let offset = add_modulo(a, multiply_modulo(self.period, k, period), period);
+
+        Ok(Some(Residue { offset, period }))
+    }
+
+    // the first step of this residue that comes after the given step
+    fn first_after(&self, step: u128) -> Option<u128> {
+        let offset = self.offset % self.period;
+        match offset > step {
+            true => Some(offset),
+            false => ((step - offset) / self.period + 1)
+                .checked_mul(self.period)?
+                .checked_add(offset),
+        }
     }
-    let a = nums[0];
-    let b = least_common_multiple(&nums[1..]);
-    a * b / greatest_common_divisor(a, b)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -36,95 +117,267 @@ impl TryFrom<char> for Direction {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Position<'a> {
-    name: &'a str,
+impl Direction {
+    fn index(&self) -> usize {
+        match self {
+            Self::Left => 0,
+            Self::Right => 1,
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Path<'a> {
-    leads_to: BTreeMap<Direction, Position<'a>>,
+fn parse_steps(value: &str) -> Result<Vec<Direction>> {
+    value
+        .chars()
+        .map(Direction::try_from)
+        .collect::<Result<Vec<_>>>()
 }
 
+// The nodes of the network, identified by their index in names
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Map<'a> {
-    steps: Vec<Direction>,
-    paths: BTreeMap<Position<'a>, Path<'a>>,
+pub struct Network {
+    names: Vec<String>,
+    ids: BTreeMap<String, usize>,
+    // the left and right node each node leads to
+    leads_to: Vec<[usize; 2]>,
 }
 
-impl<'a> TryFrom<&'a str> for Map<'a> {
+impl TryFrom<&str> for Network {
     type Error = anyhow::Error;
 
-    fn try_from(value: &'a str) -> Result<Self> {
-        let mut result = Self {
-            steps: Vec::new(),
-            paths: BTreeMap::new(),
-        };
-
-        let (steps, directions) = value.trim().split_once("\n\n").context("")?;
-
-        result.steps = steps
-            .chars()
-            .map(|s| Direction::try_from(s))
-            .collect::<Result<Vec<_>>>()?;
-
-        let mut positions = Vec::new();
-
-        let directions = directions
+    fn try_from(value: &str) -> Result<Self> {
+        let directions = value
+            .trim()
             .split("\n")
             .map(|dir| {
-                let (name, dests) = dir.split_once(" = ").context("")?;
-                let (_, dests) = dests.split_once("(").context("")?;
-                let (dests, _) = dests.split_once(")").context("")?;
-                let (left, right) = dests.split_once(", ").context("")?;
-                positions.push(Position { name });
+                let (name, dests) = dir.split_once(" = ").context("Bad node")?;
+                let (_, dests) = dests.split_once("(").context("Bad node")?;
+                let (dests, _) = dests.split_once(")").context("Bad node")?;
+                let (left, right) = dests.split_once(", ").context("Bad node")?;
                 Ok((name, left, right))
             })
             .collect::<Result<Vec<_>>>()?;
 
-        for (i, dir) in directions.iter().enumerate() {
-            let mut path = Path {
-                leads_to: BTreeMap::new(),
-            };
-            path.leads_to.entry(Direction::Left).or_insert(
-                positions
-                    .iter()
-                    .find(|po| po.name == dir.1)
-                    .context("")?
-                    .clone(),
-            );
-            path.leads_to.entry(Direction::Right).or_insert(
-                positions
-                    .iter()
-                    .find(|po| po.name == dir.2)
-                    .context("")?
-                    .clone(),
-            );
-            result.paths.insert(positions[i].clone(), path);
+        let names = directions
+            .iter()
+            .map(|(name, _, _)| name.to_string())
+            .collect::<Vec<String>>();
+        let ids = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect::<BTreeMap<String, usize>>();
+        if ids.len() != names.len() {
+            return Err(anyhow::anyhow!("Repeated node"));
+        }
+
+        let leads_to = directions
+            .iter()
+            .map(|(_, left, right)| {
+                let left = *ids.get(*left).context("Unknown node")?;
+                let right = *ids.get(*right).context("Unknown node")?;
+                Ok([left, right])
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            names,
+            ids,
+            leads_to,
+        })
+    }
+}
+
+// The nodes visited along the cycle a walk ends up in, and how many steps it takes to enter it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WalkCycle {
+    pub steps_to_enter: usize,
+    pub nodes: Vec<usize>,
+}
+
+impl Network {
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).cloned()
+    }
+
+    pub fn name(&self, id: usize) -> Option<&str> {
+        self.names.get(id).map(|name| name.as_str())
+    }
+
+    // for ids taken from the network itself
+    fn name_of(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    fn next(&self, id: usize, direction: &Direction) -> usize {
+        self.leads_to[id][direction.index()]
+    }
+
+    // every node that leads to each node
+    fn comes_from(&self) -> Vec<Vec<usize>> {
+        let mut result = vec![Vec::new(); self.len()];
+        for (id, dests) in self.leads_to.iter().enumerate() {
+            for dest in dests.iter() {
+                result[*dest].push(id);
+            }
         }
+        result
+    }
+
+    fn search(edges: &[Vec<usize>], starts: &[usize]) -> BTreeSet<usize> {
+        let mut result = starts.iter().cloned().collect::<BTreeSet<usize>>();
+        let mut queue = starts.iter().cloned().collect::<VecDeque<usize>>();
+        while let Some(id) = queue.pop_front() {
+            for next in edges[id].iter() {
+                if result.insert(*next) {
+                    queue.push_back(*next);
+                }
+            }
+        }
+        result
+    }
+
+    // nodes that can be reached from the given one, taking any direction on each step
+    pub fn reachable_from(&self, id: usize) -> Result<BTreeSet<usize>> {
+        self.names.get(id).context("Unknown node")?;
+
+        let edges = self
+            .leads_to
+            .iter()
+            .map(|dests| dests.to_vec())
+            .collect::<Vec<_>>();
+        Ok(Self::search(&edges, &[id]))
+    }
 
-        Ok(result)
+    // nodes that can not reach any destination, whatever directions they take
+    pub fn never_reaching(&self, is_destination: impl Fn(&str) -> bool) -> BTreeSet<usize> {
+        let destinations = (0..self.len())
+            .filter(|id| is_destination(self.name_of(*id)))
+            .collect::<Vec<usize>>();
+        let reaching = Self::search(&self.comes_from(), &destinations);
+        (0..self.len())
+            .filter(|id| !reaching.contains(id))
+            .collect()
+    }
+
+    // Kosaraju's algorithm, components come in topological order of the condensed graph
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        let mut finished = Vec::new();
+        let mut visited = vec![false; self.len()];
+        for root in 0..self.len() {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((id, edge)) = stack.pop() {
+                match self.leads_to[id].get(edge) {
+                    Some(next) => {
+                        stack.push((id, edge + 1));
+                        if !visited[*next] {
+                            visited[*next] = true;
+                            stack.push((*next, 0));
+                        }
+                    }
+                    None => finished.push(id),
+                }
+            }
+        }
+
+        let comes_from = self.comes_from();
+        let mut component = vec![None; self.len()];
+        let mut result: Vec<Vec<usize>> = Vec::new();
+        for root in finished.into_iter().rev() {
+            if component[root].is_some() {
+                continue;
+            }
+            component[root] = Some(result.len());
+            let mut members = Vec::new();
+            let mut stack = vec![root];
+            while let Some(id) = stack.pop() {
+                members.push(id);
+                for prev in comes_from[id].iter() {
+                    if component[*prev].is_none() {
+                        component[*prev] = Some(result.len());
+                        stack.push(*prev);
+                    }
+                }
+            }
+            members.sort();
+            result.push(members);
+        }
+        result
+    }
+
+    // the cycle a walk from the given node ends up in when repeating the instructions forever
+    pub fn cycle_under(&self, id: usize, instructions: &str) -> Result<WalkCycle> {
+        self.names.get(id).context("Unknown node")?;
+        let steps = parse_steps(instructions)?;
+        if steps.is_empty() {
+            return Err(anyhow::anyhow!("No instructions"));
+        }
+
+        let mut seen = BTreeMap::new();
+        let mut nodes = Vec::new();
+        let mut current = id;
+        let steps_to_enter = loop {
+            let state = (current, nodes.len() % steps.len());
+            if let Some(step) = seen.get(&state) {
+                break *step;
+            }
+            seen.insert(state, nodes.len());
+            nodes.push(current);
+            current = self.next(current, &steps[state.1]);
+        };
+
+        Ok(WalkCycle {
+            steps_to_enter,
+            nodes: nodes.split_off(steps_to_enter),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Map {
+    steps: Vec<Direction>,
+    network: Network,
+}
+
+impl TryFrom<&str> for Map {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        let (steps, network) = value.trim().split_once("\n\n").context("")?;
+
+        Ok(Self {
+            steps: parse_steps(steps)?,
+            network: Network::try_from(network)?,
+        })
     }
 }
 
 #[derive(Clone)]
 struct Player<'a> {
-    map: &'a Map<'a>,
-    current_position: Position<'a>,
-    start_position: Position<'a>,
+    map: &'a Map,
+    current_position: usize,
+    start_position: usize,
     current_step: usize,
-    is_destination: &'a dyn Fn(Position<'a>) -> bool,
+    is_destination: &'a dyn Fn(&str) -> bool,
 }
 
 impl<'a> Player<'a> {
-    fn new(
-        map: &'a Map<'a>,
-        start_position: Position<'a>,
-        is_destination: &'a dyn Fn(Position<'a>) -> bool,
-    ) -> Self {
+    fn new(map: &'a Map, start_position: usize, is_destination: &'a dyn Fn(&str) -> bool) -> Self {
         Self {
             map,
-            current_position: start_position.clone(),
+            current_position: start_position,
             start_position,
             current_step: 0,
             is_destination,
@@ -134,15 +387,10 @@ impl<'a> Player<'a> {
 
 impl Player<'_> {
     fn walk(&mut self) {
-        self.current_position = self
-            .map
-            .paths
-            .get(&self.current_position)
-            .unwrap()
-            .leads_to
-            .get(&self.map.steps[self.current_step % self.map.steps.len()])
-            .unwrap()
-            .clone();
+        self.current_position = self.map.network.next(
+            self.current_position,
+            &self.map.steps[self.current_step % self.map.steps.len()],
+        );
 
         self.current_step += 1;
     }
@@ -150,16 +398,14 @@ impl Player<'_> {
 
 impl Player<'_> {
     fn reset(&mut self) {
-        self.current_position = self.start_position.clone();
+        self.current_position = self.start_position;
         self.current_step = 0;
     }
 }
 
-// Todo: use ref for position
-impl<'a, 'b> Player<'b> {
-    fn is_at_destination(&'a self, position: Position<'b>) -> bool {
-        let closure = self.is_destination;
-        closure(position)
+impl Player<'_> {
+    fn is_at_destination(&self) -> bool {
+        (self.is_destination)(self.map.network.name_of(self.current_position))
     }
 }
 
@@ -168,12 +414,13 @@ struct Circuit {
     steps_per_cycle: usize,
     cycles_to_start: usize,
     cycles_to_finish: usize,
+    destinations_before_circuit: Vec<usize>,
     destination_indices: Vec<usize>,
 }
 
 impl<'a> Circuit {
     fn new(player: &'a mut Player<'_>) -> Self {
-        let mut position_after_end_of_cycle: Vec<Position> = vec![player.current_position.clone()];
+        let mut position_after_end_of_cycle = vec![player.current_position];
         let mut destination_indices = Vec::new();
 
         let steps_per_cycle = player.map.steps.len();
@@ -183,18 +430,18 @@ impl<'a> Circuit {
             for i in 0..steps_per_cycle {
                 player.walk();
 
-                if player.is_at_destination(player.current_position.clone()) {
+                if player.is_at_destination() {
                     destination_indices.push(cycle_count * steps_per_cycle + i);
                 }
             }
 
             if let Some(pos) = position_after_end_of_cycle
                 .iter()
-                .position(|p| p.name == player.current_position.name)
+                .position(|p| *p == player.current_position)
             {
                 break pos;
             } else {
-                position_after_end_of_cycle.push(player.current_position.clone());
+                position_after_end_of_cycle.push(player.current_position);
             }
 
             cycle_count += 1;
@@ -204,6 +451,12 @@ impl<'a> Circuit {
 
         let steps_until_circuit = cycles_to_start * steps_per_cycle;
 
+        let destinations_before_circuit = destination_indices
+            .iter()
+            .filter(|d| **d < steps_until_circuit)
+            .map(|d| *d + 1)
+            .collect::<Vec<_>>();
+
         let destination_indices = destination_indices
             .iter()
             .filter(|d| **d >= steps_until_circuit)
@@ -216,105 +469,172 @@ impl<'a> Circuit {
             steps_per_cycle,
             cycles_to_start,
             cycles_to_finish,
+            destinations_before_circuit,
             destination_indices,
         }
     }
 }
 
+impl Circuit {
+    fn steps_until_circuit(&self) -> u128 {
+        (self.steps_per_cycle * self.cycles_to_start) as u128
+    }
+
+    fn residues(&self) -> Vec<Residue> {
+        let period = (self.steps_per_cycle * self.cycles_to_finish) as u128;
+        self.destination_indices
+            .iter()
+            .map(|d| Residue {
+                offset: self.steps_until_circuit() + *d as u128,
+                period,
+            })
+            .collect()
+    }
+
+    fn is_destination_at(&self, step: u128) -> bool {
+        match step <= self.steps_until_circuit() {
+            true => self
+                .destinations_before_circuit
+                .iter()
+                .any(|d| *d as u128 == step),
+            false => self
+                .residues()
+                .iter()
+                .any(|r| step % r.period == r.offset % r.period),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct MultipleCircuits {
     circuits: Vec<Circuit>,
 }
 
 impl MultipleCircuits {
-    fn calculate_min_steps_to_destination(&self) -> Result<u128> {
-        let assumption = self.circuits.iter().all(|c| {
-            c.steps_per_cycle == self.circuits[0].steps_per_cycle
-                && c.cycles_to_start == self.circuits[0].cycles_to_start
-        });
-
-        if !assumption {
-            return Err(anyhow::anyhow!(
-                "Can not guarantee that this method will yield the correct result"
-            ));
-        }
-
-        /*
-         * When a circuit is found and there is at least one destination inside it, it means there will be
-         * infinite destinations, which can be found by:
-         *
-         * let mut solutions = Vec::new();
-         * let mut i = 0;
-         * loop {
-         *     for j in c.destinations_inside_circuit.iter() {
-         *         solutions.push(i * (c.cycles_to_finish * c.steps_per_cycle) + (c.cycles_to_start * c.steps_per_cycle) + j);
-         *     }
-         *     i += 1;
-         * }
-         *
-         *
-         **/
-
-        let values = self
+    // Ok(None) means the ghosts are never at their destinations at the same time
+    fn calculate_min_steps_to_destination(&self) -> Result<Option<u128>> {
+        let first = self.circuits.first().context("No ghosts")?;
+        let steps_until_all_circuits = self
             .circuits
             .iter()
-            .map(|c| (c.steps_per_cycle * c.cycles_to_start + c.destination_indices[0]) as u128)
-            .collect::<Vec<u128>>();
+            .map(|c| c.steps_until_circuit())
+            .max()
+            .unwrap_or(0);
 
-        Ok(least_common_multiple(&values))
-    }
-}
-
-pub fn run_part_1(input: String) -> Result<u128> {
-    let map = Map::try_from(input.trim())?;
-
-    let is_destination = |pos: Position| pos.name == "ZZZ";
+        // Before every ghost is inside its circuit, try each step the first ghost could finish on
+        let mut candidates = first
+            .destinations_before_circuit
+            .iter()
+            .map(|d| *d as u128)
+            .collect::<Vec<u128>>();
+        for residue in first.residues() {
+            let mut step = residue.offset;
+            while step <= steps_until_all_circuits {
+                candidates.push(step);
+                step += residue.period;
+            }
+        }
+        candidates.sort();
 
-    let mut players = map
-        .paths
-        .keys()
-        .filter(|pos| pos.name == "AAA")
-        .map(|sp| Player::new(&map, sp.clone(), &is_destination))
-        .collect::<Vec<_>>();
+        if let Some(step) = candidates
+            .into_iter()
+            .filter(|step| *step <= steps_until_all_circuits)
+            .find(|step| self.circuits.iter().all(|c| c.is_destination_at(*step)))
+        {
+            return Ok(Some(step));
+        }
 
-    let circuits = players
-        .iter_mut()
-        .map(|pl| Circuit::new(pl))
-        .collect::<Vec<_>>();
+        // After that each ghost repeats, so combine the residues of every ghost
+        let mut combined = vec![Residue {
+            offset: 0,
+            period: 1,
+        }];
+        for circuit in self.circuits.iter() {
+            let residues = circuit.residues();
+            let mut next = Vec::new();
+            for a in combined.iter() {
+                for b in residues.iter() {
+                    if let Some(residue) = a.combine(b)? {
+                        next.push(residue);
+                    }
+                }
+            }
+            next.sort();
+            next.dedup();
+            combined = next;
+        }
 
-    let max_possible_value_before_solving_with_circuits = circuits
-        .iter()
-        .map(|c| c.steps_per_cycle * (c.cycles_to_start + c.cycles_to_finish))
-        .max()
-        .unwrap();
+        combined
+            .iter()
+            .map(|r| {
+                r.first_after(steps_until_all_circuits)
+                    .context("Result is too large")
+            })
+            .collect::<Result<Vec<u128>>>()
+            .map(|steps| steps.into_iter().min())
+    }
+}
 
-    for k in 1..=max_possible_value_before_solving_with_circuits {
-        players.iter_mut().for_each(|pl| pl.walk());
+// Chooses nodes by their names
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Selector {
+    Name(String),
+    Suffix(String),
+    // '*' matches any run of characters and '?' matches exactly one
+    Glob(String),
+}
 
-        if players
-            .iter()
-            .all(|pl| pl.is_at_destination(pl.current_position.clone()))
-        {
-            let k = k as u128;
-            return Ok(k);
+impl Selector {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Name(n) => name == n,
+            Self::Suffix(s) => name.ends_with(s.as_str()),
+            Self::Glob(g) => {
+                let pattern = g.chars().collect::<Vec<char>>();
+                let name = name.chars().collect::<Vec<char>>();
+
+                // matches[j] tells whether the pattern so far matches the first j characters
+                let mut matches = vec![false; name.len() + 1];
+                matches[0] = true;
+                for p in pattern.iter() {
+                    let previous = matches.clone();
+                    matches[0] = previous[0] && p == &'*';
+                    for j in 1..=name.len() {
+                        matches[j] = match p {
+                            '*' => previous[j] || matches[j - 1],
+                            '?' => previous[j - 1],
+                            c => previous[j - 1] && &name[j - 1] == c,
+                        };
+                    }
+                }
+                matches[name.len()]
+            }
         }
     }
+}
 
-    let multiple_circuits = MultipleCircuits { circuits };
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GhostSteps {
+    pub start: String,
+    // None when this ghost never reaches a destination
+    pub steps: Option<u128>,
+}
 
-    multiple_circuits.calculate_min_steps_to_destination()
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Solution {
+    pub ghosts: Vec<GhostSteps>,
+    // None when the ghosts are never at their destinations at the same time
+    pub steps: Option<u128>,
 }
 
-pub fn run_part_2(input: String) -> Result<u128> {
+pub fn solve(input: &str, start: &Selector, destination: &Selector) -> Result<Solution> {
     let map = Map::try_from(input.trim())?;
 
-    let is_destination = |pos: Position| pos.name.chars().last() == Some('Z');
+    let is_destination = |name: &str| destination.matches(name);
 
-    let mut players = map
-        .paths
-        .keys()
-        .filter(|pos| pos.name.chars().last() == Some('A'))
-        .map(|sp| Player::new(&map, sp.clone(), &is_destination))
+    let mut players = (0..map.network.len())
+        .filter(|id| start.matches(map.network.name_of(*id)))
+        .map(|sp| Player::new(&map, sp, &is_destination))
         .collect::<Vec<_>>();
 
     let circuits = players
@@ -322,33 +642,57 @@ pub fn run_part_2(input: String) -> Result<u128> {
         .map(|pl| Circuit::new(pl))
         .collect::<Vec<_>>();
 
-    let max_possible_value_before_circuits = circuits
+    let ghosts = players
         .iter()
-        .map(|c| c.steps_per_cycle * (c.cycles_to_start + c.cycles_to_finish))
-        .max()
-        .unwrap();
+        .zip(circuits.iter())
+        .map(|(pl, c)| {
+            let single_circuit = MultipleCircuits {
+                circuits: vec![c.clone()],
+            };
+            Ok(GhostSteps {
+                start: map.network.name_of(pl.start_position).to_string(),
+                steps: single_circuit.calculate_min_steps_to_destination()?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    for k in 1..=max_possible_value_before_circuits {
-        players.iter_mut().for_each(|pl| pl.walk());
+    let multiple_circuits = MultipleCircuits { circuits };
 
-        if players
-            .iter()
-            .all(|pl| pl.is_at_destination(pl.current_position.clone()))
-        {
-            let k = k as u128;
-            return Ok(k);
-        }
-    }
+    Ok(Solution {
+        ghosts,
+        steps: multiple_circuits.calculate_min_steps_to_destination()?,
+    })
+}
 
-    let multiple_circuits = MultipleCircuits { circuits };
+fn never_at_destination() -> anyhow::Error {
+    anyhow::anyhow!("The ghosts are never at their destinations at the same time")
+}
+
+pub fn run_part_1(input: String) -> Result<u128> {
+    let start = Selector::Name("AAA".to_string());
+    let destination = Selector::Name("ZZZ".to_string());
 
-    multiple_circuits.calculate_min_steps_to_destination()
+    solve(&input, &start, &destination)?
+        .steps
+        .ok_or_else(never_at_destination)
+}
+
+pub fn run_part_2(input: String) -> Result<u128> {
+    let start = Selector::Suffix("A".to_string());
+    let destination = Selector::Suffix("Z".to_string());
+
+    solve(&input, &start, &destination)?
+        .steps
+        .ok_or_else(never_at_destination)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::d08::run_part_1;
     use crate::d08::run_part_2;
+    use crate::d08::solve;
+    use crate::d08::Network;
+    use crate::d08::Selector;
     use std::fs::read_to_string;
 
     #[test]
@@ -374,4 +718,99 @@ mod tests {
         let input = read_to_string("src/d08/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 11188774513823);
     }
+
+    #[test]
+    fn part_2_different_circuits() {
+        // 11A enters a circuit of 5 after 2 steps, with destinations at 2 and 4 steps.
+        // 22A enters a circuit of 7 after 3 steps, with a destination before it and one inside
+        let ghosts = "L
+
+11A = (11P, 11P)
+11P = (11Z, 11Z)
+11Z = (11B, 11B)
+11B = (12Z, 12Z)
+12Z = (11C, 11C)
+11C = (11D, 11D)
+11D = (11Z, 11Z)
+22A = (22Z, 22Z)
+22Z = (22B, 22B)
+22B = (22C, 22C)
+22C = (22D, 22D)
+22D = (23Z, 23Z)
+23Z = (22F, 22F)
+22F = (22G, 22G)
+22G = (22H, 22H)
+22H = (22I, 22I)
+22I = (22C, 22C)";
+        assert_eq!(run_part_2(ghosts.to_string()).unwrap(), 12);
+
+        // shrinking the circuit of 22A to 5 leaves no step in common
+        let ghosts = ghosts.replace("22G = (22H, 22H)", "22G = (22C, 22C)");
+        assert!(run_part_2(ghosts).is_err());
+    }
+
+    #[test]
+    fn network_analysis() {
+        let input = read_to_string("src/d08/test2.txt").expect("could not read file");
+        let (_, nodes) = input.trim().split_once("\n\n").unwrap();
+        let network = Network::try_from(nodes).unwrap();
+        let id = |name| network.id(name).unwrap();
+        let names = |ids: Vec<usize>| {
+            ids.iter()
+                .map(|id| network.name(*id).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let reachable = network.reachable_from(id("11A")).unwrap();
+        assert_eq!(
+            names(reachable.into_iter().collect()),
+            ["11A", "11B", "11Z", "XXX"]
+        );
+
+        let components = network
+            .strongly_connected_components()
+            .into_iter()
+            .map(names)
+            .collect::<Vec<_>>();
+        assert_eq!(components.len(), 5);
+        assert!(components.contains(&vec!["11B", "11Z"]));
+        assert!(components.contains(&vec!["22B", "22C", "22Z"]));
+        assert_eq!(components.last().unwrap(), &["XXX"]);
+
+        let cycle = network.cycle_under(id("11A"), "LR").unwrap();
+        assert_eq!(cycle.steps_to_enter, 1);
+        assert_eq!(names(cycle.nodes), ["11B", "11Z"]);
+
+        let never = network.never_reaching(|name| name.ends_with('Z'));
+        assert_eq!(names(never.into_iter().collect()), ["XXX"]);
+
+        let unknown = network.len();
+        assert_eq!(network.name(unknown), None);
+        assert!(network.reachable_from(unknown).is_err());
+        assert!(network.cycle_under(unknown, "LR").is_err());
+    }
+
+    #[test]
+    fn custom_selectors() {
+        let input = read_to_string("src/d08/test2.txt").expect("could not read file");
+        let start = Selector::Glob("??A".to_string());
+        let destination = Selector::Glob("*Z".to_string());
+
+        let solution = solve(&input, &start, &destination).unwrap();
+        let ghosts = solution
+            .ghosts
+            .iter()
+            .map(|g| (g.start.as_str(), g.steps))
+            .collect::<Vec<_>>();
+        assert_eq!(ghosts, [("11A", Some(2)), ("22A", Some(3))]);
+        assert_eq!(solution.steps, Some(6));
+
+        let start = Selector::Name("XXX".to_string());
+        let solution = solve(&input, &start, &destination).unwrap();
+        assert_eq!(solution.ghosts[0].steps, None);
+        assert_eq!(solution.steps, None);
+
+        assert!(Selector::Glob("1*Z".to_string()).matches("11Z"));
+        assert!(!Selector::Glob("1?Z".to_string()).matches("1Z"));
+    }
 }
@@ -1,8 +1,89 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
 use anyhow::{Context, Result};
 
+fn greatest_common_divisor(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        return a.abs();
+    }
+    greatest_common_divisor(b, a % b)
+}
+
+// Fraction in lowest terms, with a positive denominator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Rational {
+    pub fn new(numerator: i128, denominator: i128) -> Result<Self> {
+        if denominator == 0 {
+            return Err(anyhow::anyhow!("Zero denominator"));
+        }
+        let gcd = greatest_common_divisor(numerator, denominator);
+        let sign = denominator.signum();
+        Ok(Self {
+            numerator: sign * numerator / gcd,
+            denominator: sign * denominator / gcd,
+        })
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.denominator
+    }
+}
+
+// Same as comparing the cross products a * d and c * b, without overflowing: compares the
+// whole parts first, then the remainders by flipping them, like a continued fraction
+fn compare_fractions(a: i128, b: i128, c: i128, d: i128) -> Ordering {
+    let whole = a.div_euclid(b).cmp(&c.div_euclid(d));
+    if whole != Ordering::Equal {
+        return whole;
+    }
+    match (a.rem_euclid(b), c.rem_euclid(d)) {
+        (0, 0) => Ordering::Equal,
+        (0, _) => Ordering::Less,
+        (_, 0) => Ordering::Greater,
+        (r, s) => compare_fractions(d, s, b, r),
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_fractions(
+            self.numerator,
+            self.denominator,
+            other.numerator,
+            other.denominator,
+        )
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.denominator {
+            1 => write!(f, "{}", self.numerator),
+            _ => write!(f, "{}/{}", self.numerator, self.denominator),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Sequence {
-    levels: Vec<Vec<isize>>,
+pub struct Sequence {
+    // the values followed by each level of their differences, down to the first level of zeros
+    levels: Vec<Vec<i128>>,
 }
 
 impl TryFrom<&str> for Sequence {
@@ -11,63 +92,132 @@ impl TryFrom<&str> for Sequence {
     fn try_from(value: &str) -> Result<Self> {
         let original = value
             .split(" ")
-            .map(|v| v.parse::<isize>().context("Error in parse"))
-            .collect::<Result<Vec<isize>>>()?;
+            .map(|v| v.parse::<i128>().context("Error in parse"))
+            .collect::<Result<Vec<i128>>>()?;
 
-        Ok(Self {
+        if original.is_empty() {
+            return Err(anyhow::anyhow!("Empty list"));
+        }
+
+        let mut result = Self {
             levels: vec![original],
-        })
+        };
+        result.calculate_levels()?;
+
+        Ok(result)
     }
 }
 
 impl Sequence {
-    fn calculate_next_level_in_loop(&mut self) -> Result<()> {
-        let last_values = self.levels.iter().last().context("Empty list")?;
+    fn calculate_levels(&mut self) -> Result<()> {
+        loop {
+            let last_values = self.levels.iter().last().context("Empty list")?;
 
-        if last_values.iter().all(|v| *v == 0) {
-            return Ok(());
-        }
+            if last_values.iter().all(|v| *v == 0) {
+                return Ok(());
+            }
+
+            if last_values.len() == 1 {
+                return Err(anyhow::anyhow!(
+                    "Differences never reach zero, not enough values to extrapolate"
+                ));
+            }
 
-        let mut to_add = Vec::new();
-        for k in 0..last_values.len() - 1 {
-            to_add.push(last_values[k + 1] - last_values[k]);
+            let to_add = last_values
+                .windows(2)
+                .map(|w| w[1].checked_sub(w[0]).context("Difference overflows"))
+                .collect::<Result<Vec<i128>>>()?;
+
+            self.levels.push(to_add);
         }
+    }
 
-        self.levels.push(to_add);
+    // degree of the polynomial generating the values, zero when they are all zero
+    pub fn degree(&self) -> usize {
+        self.levels.len().saturating_sub(2)
+    }
+
+    // Newton's forward differences: f(n) = sum of C(n, k) * first difference of level k,
+    // where n counts from the first value and may be negative or past the last one
+    pub fn value_at(&self, index: i128) -> Result<i128> {
+        let mut result: i128 = 0;
+        let mut binomial: i128 = 1;
 
-        self.calculate_next_level_in_loop()
+        for (k, level) in self.levels.iter().enumerate() {
+            if k > 0 {
+                let k = k as i128;
+                // C(n, k) = C(n, k - 1) * (n - k + 1) / k, where the division is always exact
+                binomial = index
+                    .checked_sub(k - 1)
+                    .and_then(|factor| binomial.checked_mul(factor))
+                    .context("Value overflows")?
+                    / k;
+            }
+
+            let term = binomial.checked_mul(level[0]).context("Value overflows")?;
+            result = result.checked_add(term).context("Value overflows")?;
+        }
+
+        Ok(result)
     }
 
-    fn prev_value(&self) -> Result<isize> {
-        let sum = self
-            .levels
-            .iter()
-            .enumerate()
-            .map(|(i, seq)| {
-                let multiply_by = if i % 2 == 0 { 1 } else { -1 };
-                let val = seq.iter().next().context("Empty list")?;
-                Ok(*val * multiply_by)
-            })
-            .collect::<Result<Vec<isize>>>()?
-            .iter()
-            .sum();
+    // Coefficients of the polynomial generating the values, from the constant term up,
+    // where n counts from the first value. Expands each C(n, k) of Newton's formula into
+    // powers of n, over the common denominator degree!
+    pub fn coefficients(&self) -> Result<Vec<Rational>> {
+        let degree = self.degree();
+        let denominator = (1..=degree as i128)
+            .try_fold(1i128, |acc, k| acc.checked_mul(k))
+            .context("Coefficient overflows")?;
+
+        let mut numerators = vec![0i128; degree + 1];
+        // n * (n - 1) * ... * (n - k + 1), starting from k = 0
+        let mut falling_factorial = vec![1i128];
+        // degree! / k!
+        let mut scale = denominator;
+
+        for (k, level) in self.levels.iter().take(degree + 1).enumerate() {
+            if k > 0 {
+                let previous = falling_factorial.clone();
+                falling_factorial.push(0);
+                for j in 0..falling_factorial.len() {
+                    let shifted = if j > 0 { previous[j - 1] } else { 0 };
+                    let multiplied = match previous.get(j) {
+                        Some(v) => v
+                            .checked_mul(k as i128 - 1)
+                            .context("Coefficient overflows")?,
+                        None => 0,
+                    };
+                    falling_factorial[j] = shifted
+                        .checked_sub(multiplied)
+                        .context("Coefficient overflows")?;
+                }
+                scale /= k as i128;
+            }
+
+            let factor = level[0]
+                .checked_mul(scale)
+                .context("Coefficient overflows")?;
+            for (j, v) in falling_factorial.iter().enumerate() {
+                numerators[j] = v
+                    .checked_mul(factor)
+                    .and_then(|term| numerators[j].checked_add(term))
+                    .context("Coefficient overflows")?;
+            }
+        }
 
-        Ok(sum)
+        numerators
+            .into_iter()
+            .map(|n| Rational::new(n, denominator))
+            .collect()
     }
 
-    fn next_value(&self) -> Result<isize> {
-        let sum = self
-            .levels
-            .iter()
-            .map(|seq| {
-                let val = seq.iter().last().context("Empty list")?;
-                Ok(*val)
-            })
-            .collect::<Result<Vec<isize>>>()?
-            .iter()
-            .sum();
+    pub fn prev_value(&self) -> Result<i128> {
+        self.value_at(-1)
+    }
 
-        Ok(sum)
+    pub fn next_value(&self) -> Result<i128> {
+        self.value_at(self.levels[0].len() as i128)
     }
 }
 
@@ -90,41 +240,35 @@ impl TryFrom<&str> for Sequences {
 }
 
 pub fn run_part_1(input: String) -> Result<isize> {
-    let mut sequences = Sequences::try_from(input.trim())?;
+    let sequences = Sequences::try_from(input.trim())?;
 
     let next_values = sequences
         .values
-        .iter_mut()
-        .map(|seq| {
-            seq.calculate_next_level_in_loop()?;
-            let next_value = seq.next_value()?;
-            Ok(next_value)
-        })
-        .collect::<Result<Vec<isize>>>()?;
+        .iter()
+        .map(|seq| seq.next_value())
+        .collect::<Result<Vec<i128>>>()?;
 
-    Ok(next_values.iter().sum())
+    isize::try_from(next_values.iter().sum::<i128>()).context("Result too large")
 }
 
 pub fn run_part_2(input: String) -> Result<isize> {
-    let mut sequences = Sequences::try_from(input.trim())?;
+    let sequences = Sequences::try_from(input.trim())?;
 
     let prev_values = sequences
         .values
-        .iter_mut()
-        .map(|seq| {
-            seq.calculate_next_level_in_loop()?;
-            let prev_value = seq.prev_value()?;
-            Ok(prev_value)
-        })
-        .collect::<Result<Vec<isize>>>()?;
+        .iter()
+        .map(|seq| seq.prev_value())
+        .collect::<Result<Vec<i128>>>()?;
 
-    Ok(prev_values.iter().sum())
+    isize::try_from(prev_values.iter().sum::<i128>()).context("Result too large")
 }
 
 #[cfg(test)]
 mod tests {
     use crate::d09::run_part_1;
     use crate::d09::run_part_2;
+    use crate::d09::Rational;
+    use crate::d09::Sequence;
     use std::fs::read_to_string;
 
     #[test]
@@ -150,4 +294,91 @@ mod tests {
         let input = read_to_string("src/d09/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 889);
     }
+
+    #[test]
+    fn value_at_any_index() {
+        // n^3 - 2n for n = 0..5
+        let sequence = Sequence::try_from("0 -1 4 21 56").unwrap();
+        assert_eq!(sequence.degree(), 3);
+        assert_eq!(sequence.value_at(5).unwrap(), 115);
+        assert_eq!(sequence.value_at(-3).unwrap(), -21);
+        assert_eq!(
+            sequence.value_at(1_000_000).unwrap(),
+            999_999_999_998_000_000
+        );
+
+        assert_eq!(Sequence::try_from("7 7 7").unwrap().degree(), 0);
+        assert!(Sequence::try_from("1 2 4 8 16").is_err());
+        assert!(Sequence::try_from("170141183460469231731687303715884105727 -1").is_err());
+        assert!(Sequence::try_from("0 -1 4 21 56")
+            .unwrap()
+            .value_at(i128::MAX / 2)
+            .is_err());
+    }
+
+    #[test]
+    fn coefficients_reproduce_values() {
+        let input = read_to_string("src/d09/test.txt").expect("could not read file");
+        let inputs = input.trim().split("\n").chain([
+            "0 -1 4 21 56",
+            "0 1 3 6 10 15",
+            "5 2 -7 -22 -43 -63 -61 5",
+        ]);
+
+        for line in inputs {
+            let sequence = Sequence::try_from(line).unwrap();
+            let coefficients = sequence.coefficients().unwrap();
+            assert_eq!(coefficients.len(), sequence.degree() + 1);
+
+            for (n, value) in line.split(" ").enumerate() {
+                // Horner's method, keeping the fraction unreduced
+                let (numerator, denominator) =
+                    coefficients.iter().rev().fold((0, 1), |(num, den), c| {
+                        (
+                            num * n as i128 * c.denominator() + c.numerator() * den,
+                            den * c.denominator(),
+                        )
+                    });
+                assert_eq!(numerator, value.parse::<i128>().unwrap() * denominator);
+            }
+        }
+
+        let formula = |line| {
+            Sequence::try_from(line)
+                .unwrap()
+                .coefficients()
+                .unwrap()
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(formula("0 -1 4 21 56"), ["0", "-2", "0", "1"]);
+        assert_eq!(formula("0 1 3 6 10 15"), ["0", "1/2", "1/2"]);
+        assert_eq!(
+            Sequence::try_from("4 4").unwrap().coefficients().unwrap(),
+            [Rational::new(4, 1).unwrap()]
+        );
+    }
+
+    #[test]
+    fn rationals() {
+        let rational = |n, d| Rational::new(n, d).unwrap();
+
+        assert_eq!(rational(6, -4), rational(-3, 2));
+        assert_eq!(rational(6, -4).numerator(), -3);
+        assert_eq!(rational(6, -4).denominator(), 2);
+        assert!(Rational::new(1, 0).is_err());
+
+        assert!(rational(1, 3) < rational(1, 2));
+        assert!(rational(-1, 2) < rational(-1, 3));
+        assert!(rational(7, 2) > rational(10, 3));
+        assert!(rational(-7, 2) < rational(-10, 3));
+        assert!(rational(0, 5) < rational(1, 1000));
+        // the cross products overflow an i128
+        assert!(rational(i128::MAX, i128::MAX - 1) < rational(i128::MAX - 1, i128::MAX - 2));
+        assert_eq!(
+            rational(2, 4).cmp(&rational(1, 2)),
+            std::cmp::Ordering::Equal
+        );
+    }
 }
@@ -1,7 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Display;
 
 use anyhow::{Context, Result};
 
+use crate::polygon;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum Tile {
     Ground,
@@ -27,11 +30,32 @@ impl TryFrom<char> for Tile {
             '7' => Ok(Self::SouthToWest),
             'F' => Ok(Self::SouthToEast),
             'S' => Ok(Self::StartingPosition),
+            '│' => Ok(Self::NorthToSouth),
+            '─' => Ok(Self::EastToWest),
+            '└' => Ok(Self::NorthToEast),
+            '┘' => Ok(Self::NorthToWest),
+            '┐' => Ok(Self::SouthToWest),
+            '┌' => Ok(Self::SouthToEast),
             _ => Err(anyhow::anyhow!("Invalid character")),
         }
     }
 }
 
+impl Tile {
+    fn box_drawing(&self) -> char {
+        match self {
+            Self::Ground => '.',
+            Self::NorthToSouth => '│',
+            Self::EastToWest => '─',
+            Self::NorthToEast => '└',
+            Self::NorthToWest => '┘',
+            Self::SouthToWest => '┐',
+            Self::SouthToEast => '┌',
+            Self::StartingPosition => 'S',
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum Direction {
     East,
@@ -40,6 +64,48 @@ enum Direction {
     South,
 }
 
+impl Direction {
+    fn opposite(&self) -> Self {
+        match self {
+            Self::East => Self::West,
+            Self::North => Self::South,
+            Self::West => Self::East,
+            Self::South => Self::North,
+        }
+    }
+}
+
+impl Tile {
+    // the directions a pipe leads to
+    fn connections(&self) -> Vec<Direction> {
+        match self {
+            Self::NorthToSouth => vec![Direction::North, Direction::South],
+            Self::EastToWest => vec![Direction::East, Direction::West],
+            Self::NorthToEast => vec![Direction::North, Direction::East],
+            Self::NorthToWest => vec![Direction::North, Direction::West],
+            Self::SouthToWest => vec![Direction::South, Direction::West],
+            Self::SouthToEast => vec![Direction::South, Direction::East],
+            Self::Ground | Self::StartingPosition => vec![],
+        }
+    }
+
+    fn from_connections(a: &Direction, b: &Direction) -> Option<Self> {
+        [
+            Self::NorthToSouth,
+            Self::EastToWest,
+            Self::NorthToEast,
+            Self::NorthToWest,
+            Self::SouthToWest,
+            Self::SouthToEast,
+        ]
+        .into_iter()
+        .find(|t| {
+            let connections = t.connections();
+            a != b && connections.contains(a) && connections.contains(b)
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct Coordinate {
     lattitude: usize,
@@ -47,30 +113,30 @@ struct Coordinate {
 }
 
 impl Coordinate {
-    fn find_by_direction(&self, dir: &Direction) -> Self {
+    fn find_by_direction(&self, dir: &Direction) -> Option<Self> {
         match dir {
-            Direction::East => Self {
+            Direction::East => Some(Self {
                 lattitude: self.lattitude,
-                longitude: self.longitude + 1,
-            },
-            Direction::North => Self {
-                lattitude: self.lattitude + 1,
+                longitude: self.longitude.checked_add(1)?,
+            }),
+            Direction::North => Some(Self {
+                lattitude: self.lattitude.checked_add(1)?,
                 longitude: self.longitude,
-            },
-            Direction::West => Self {
+            }),
+            Direction::West => Some(Self {
                 lattitude: self.lattitude,
-                longitude: self.longitude - 1,
-            },
-            Direction::South => Self {
-                lattitude: self.lattitude - 1,
+                longitude: self.longitude.checked_sub(1)?,
+            }),
+            Direction::South => Some(Self {
+                lattitude: self.lattitude.checked_sub(1)?,
                 longitude: self.longitude,
-            },
+            }),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Map {
+pub struct Map {
     coordinates: BTreeMap<Coordinate, Tile>,
     size: (usize, usize),
 }
@@ -85,9 +151,12 @@ impl TryFrom<&str> for Map {
         let no_of_cols = value
             .lines()
             .next()
-            .unwrap()
+            .context("Empty map")?
             .chars()
             .fold(0, |acc, _| acc + 1);
+        if value.lines().any(|line| line.chars().count() != no_of_cols) {
+            return Err(anyhow::anyhow!("Lines of different sizes"));
+        }
 
         for (i, line) in value.lines().enumerate() {
             for (j, char) in line.chars().enumerate() {
@@ -122,27 +191,151 @@ impl Map {
             None => Err(anyhow::anyhow!("Starting position not found")),
         }
     }
+
+    // the pipe hidden under S, given by the neighbours that connect back to it
+    fn starting_tile(&self) -> Result<(Coordinate, Tile)> {
+        let start = self.initial_coordinates()?;
+
+        let connected = [
+            Direction::East,
+            Direction::North,
+            Direction::West,
+            Direction::South,
+        ]
+        .into_iter()
+        .filter(|dir| {
+            start
+                .find_by_direction(dir)
+                .and_then(|co| self.coordinates.get(&co))
+                .is_some_and(|t| t.connections().contains(&dir.opposite()))
+        })
+        .collect::<Vec<Direction>>();
+
+        match connected[..] {
+            [ref a, ref b] => {
+                let tile = Tile::from_connections(a, b).context("Invalid starting position")?;
+                Ok((start, tile))
+            }
+            [] | [_] => Err(anyhow::anyhow!(
+                "Starting position connects to {} pipes, expected 2",
+                connected.len()
+            )),
+            _ => Err(anyhow::anyhow!(
+                "Starting position is ambiguous, it connects to {} pipes",
+                connected.len()
+            )),
+        }
+    }
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let options = RenderOptions::default();
+        let rendered = self.render_rows(&options, &BTreeSet::new(), &BTreeSet::new());
+        write!(f, "{}", rendered.join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RenderOptions {
+    pub main_loop: bool,
+    pub enclosed: bool,
+    pub start: bool,
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED_BACKGROUND: &str = "\x1b[41m";
+const BOLD_YELLOW: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+impl Map {
+    // Draws the map with box-drawing characters, which parse back into the same map,
+    // optionally coloring the main loop, the tiles it encloses and the starting position
+    pub fn render(&self, options: &RenderOptions) -> Result<String> {
+        let mut loop_finder = LoopFinder::new(self)?;
+        while loop_finder.navigate()? {}
+
+        let (rows, _) = self.size;
+        let main_loop = loop_finder
+            .loop_coordinates
+            .keys()
+            .map(|co| (rows - co.lattitude - 1, co.longitude))
+            .collect::<BTreeSet<(usize, usize)>>();
+        let enclosed = flood_enclosed_tiles(&loop_finder);
+
+        Ok(self.render_rows(options, &main_loop, &enclosed).join("\n"))
+    }
+
+    fn render_rows(
+        &self,
+        options: &RenderOptions,
+        main_loop: &BTreeSet<(usize, usize)>,
+        enclosed: &BTreeSet<(usize, usize)>,
+    ) -> Vec<String> {
+        let (rows, cols) = self.size;
+
+        (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .map(|col| {
+                        let tile = self
+                            .coordinates
+                            .get(&Coordinate {
+                                lattitude: rows - row - 1,
+                                longitude: col,
+                            })
+                            .unwrap_or(&Tile::Ground);
+
+                        let color = if options.start && tile == &Tile::StartingPosition {
+                            Some(BOLD_YELLOW)
+                        } else if options.main_loop && main_loop.contains(&(row, col)) {
+                            Some(GREEN)
+                        } else if options.enclosed && enclosed.contains(&(row, col)) {
+                            Some(RED_BACKGROUND)
+                        } else {
+                            None
+                        };
+
+                        match color {
+                            Some(color) => format!("{}{}{}", color, tile.box_drawing(), RESET),
+                            None => tile.box_drawing().to_string(),
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct LoopFinder<'a> {
     map: &'a Map,
+    start_position: Coordinate,
     current_position: Coordinate,
-    start_direction: Direction,
     current_direction: Direction,
     loop_coordinates: BTreeMap<Coordinate, Tile>,
+    // the loop in the order it was walked
+    path: Vec<Coordinate>,
 }
 
 impl<'a> LoopFinder<'a> {
     fn new(map: &'a Map) -> Result<Self> {
-        let current_position = map.initial_coordinates()?;
+        let (start_position, starting_tile) = map.starting_tile()?;
+        let current_direction = starting_tile.connections()[0].clone();
+        let current_position = start_position
+            .find_by_direction(&current_direction)
+            .context("Loop leaves the map")?;
+
+        let mut loop_coordinates = BTreeMap::new();
+        loop_coordinates.insert(start_position.clone(), starting_tile);
 
         let result = Self {
             map,
+            path: vec![start_position.clone()],
+            start_position,
             current_position,
-            start_direction: Direction::North,
-            current_direction: Direction::North,
-            loop_coordinates: BTreeMap::new(),
+            current_direction,
+            loop_coordinates,
         };
 
         Ok(result)
@@ -151,130 +344,35 @@ impl<'a> LoopFinder<'a> {
 
 impl LoopFinder<'_> {
     fn navigate(&mut self) -> Result<bool> {
-        let (current_coordinate, current_tile) = self
+        if self.current_position == self.start_position {
+            // return false to end navigation loop
+            return Ok(false);
+        }
+
+        let current_tile = self
             .map
             .coordinates
-            .get_key_value(&self.current_position)
+            .get(&self.current_position)
             .context("Coordinate not found")?;
 
-        // Handle starting position
-        if current_tile == &Tile::StartingPosition {
-            if self.loop_coordinates.len() == 0 {
-                // start of loop, need to pick a direction
-                let east = self
-                    .map
-                    .coordinates
-                    .get(&current_coordinate.find_by_direction(&Direction::East));
-                if east == Some(&Tile::EastToWest)
-                    || east == Some(&Tile::NorthToWest)
-                    || east == Some(&Tile::SouthToWest)
-                {
-                    self.start_direction = Direction::East;
-                    self.current_direction = Direction::East;
-                    self.loop_coordinates
-                        .insert(self.current_position.clone(), east.unwrap().clone());
-                    self.current_position =
-                        current_coordinate.find_by_direction(&self.current_direction);
-                    return Ok(true);
-                }
-
-                let north = self
-                    .map
-                    .coordinates
-                    .get(&current_coordinate.find_by_direction(&Direction::North));
-                if north == Some(&Tile::NorthToSouth)
-                    || north == Some(&Tile::SouthToWest)
-                    || north == Some(&Tile::SouthToEast)
-                {
-                    self.start_direction = Direction::North;
-                    self.current_direction = Direction::North;
-                    self.loop_coordinates
-                        .insert(self.current_position.clone(), north.unwrap().clone());
-                    self.current_position =
-                        current_coordinate.find_by_direction(&self.current_direction);
-                    return Ok(true);
-                }
-
-                let west = self
-                    .map
-                    .coordinates
-                    .get(&current_coordinate.find_by_direction(&Direction::West));
-                if west == Some(&Tile::EastToWest)
-                    || west == Some(&Tile::SouthToEast)
-                    || west == Some(&Tile::SouthToWest)
-                {
-                    self.start_direction = Direction::West;
-                    self.current_direction = Direction::West;
-                    self.loop_coordinates
-                        .insert(self.current_position.clone(), west.unwrap().clone());
-                    self.current_position =
-                        current_coordinate.find_by_direction(&self.current_direction);
-                    return Ok(true);
-                }
-
-                // a valid loop needs at least two entrances, so we expect to have found at least
-                // one ending of it.
-                return Err(anyhow::anyhow!("Invalid loop"));
-            } else {
-                // end of loop, need to change S into its actual tile format
-                let starting_tile = match (&self.start_direction, &self.current_direction) {
-                    (Direction::East, Direction::East) => Tile::EastToWest,
-                    (Direction::East, Direction::North) => Tile::SouthToEast,
-                    (Direction::East, Direction::South) => Tile::NorthToEast,
-                    (Direction::North, Direction::East) => Tile::NorthToWest,
-                    (Direction::North, Direction::North) => Tile::NorthToSouth,
-                    (Direction::North, Direction::West) => Tile::NorthToEast,
-                    (Direction::West, Direction::North) => Tile::SouthToWest,
-                    (Direction::West, Direction::West) => Tile::EastToWest,
-                    (Direction::West, Direction::South) => Tile::NorthToWest,
-                    _ => unreachable!(),
-                };
-
-                self.loop_coordinates
-                    .insert(self.current_position.clone(), starting_tile);
-
-                // return false to end navigation loop
-                return Ok(false);
-            }
+        // leave the pipe through the end we did not come in from
+        let came_from = self.current_direction.opposite();
+        let connections = current_tile.connections();
+        if !connections.contains(&came_from) {
+            return Err(anyhow::anyhow!("Invalid loop"));
         }
+        self.current_direction = connections
+            .into_iter()
+            .find(|dir| dir != &came_from)
+            .context("Invalid loop")?;
 
-        // Handle cases other than starting position
-        match (current_tile, &self.current_direction) {
-            (Tile::NorthToEast, Direction::West) => {
-                self.current_direction = Direction::North;
-            }
-            (Tile::NorthToEast, Direction::South) => {
-                self.current_direction = Direction::East;
-            }
-            (Tile::NorthToWest, Direction::East) => {
-                self.current_direction = Direction::North;
-            }
-            (Tile::NorthToWest, Direction::South) => {
-                self.current_direction = Direction::West;
-            }
-            (Tile::SouthToWest, Direction::East) => {
-                self.current_direction = Direction::South;
-            }
-            (Tile::SouthToWest, Direction::North) => {
-                self.current_direction = Direction::West;
-            }
-            (Tile::SouthToEast, Direction::North) => {
-                self.current_direction = Direction::East;
-            }
-            (Tile::SouthToEast, Direction::West) => {
-                self.current_direction = Direction::South;
-            }
-            (Tile::NorthToSouth, Direction::North) => {}
-            (Tile::NorthToSouth, Direction::South) => {}
-            (Tile::EastToWest, Direction::East) => {}
-            (Tile::EastToWest, Direction::West) => {}
-            _ => {
-                return Err(anyhow::anyhow!("Invalid loop"));
-            }
-        };
         self.loop_coordinates
             .insert(self.current_position.clone(), current_tile.clone());
-        self.current_position = current_coordinate.find_by_direction(&self.current_direction);
+        self.path.push(self.current_position.clone());
+        self.current_position = self
+            .current_position
+            .find_by_direction(&self.current_direction)
+            .context("Loop leaves the map")?;
 
         Ok(true)
     }
@@ -330,10 +428,94 @@ pub fn run_part_2(input: String) -> Result<usize> {
     Ok(result)
 }
 
+// Same as part 2, using the shoelace formula and Pick's theorem over the loop
+pub fn enclosed_area(input: String) -> Result<usize> {
+    let map = Map::try_from(input.trim())?;
+    let mut loop_finder = LoopFinder::new(&map)?;
+    while loop_finder.navigate()? {}
+
+    let vertices = loop_finder
+        .path
+        .iter()
+        .map(|co| (co.lattitude as i64, co.longitude as i64))
+        .collect::<Vec<(i64, i64)>>();
+
+    Ok(polygon::interior_tiles(&vertices) as usize)
+}
+
+// The (row, column) of each tile enclosed by the loop, counting from the top left corner
+pub fn enclosed_tiles(input: String) -> Result<BTreeSet<(usize, usize)>> {
+    let map = Map::try_from(input.trim())?;
+    let mut loop_finder = LoopFinder::new(&map)?;
+    while loop_finder.navigate()? {}
+
+    Ok(flood_enclosed_tiles(&loop_finder))
+}
+
+fn flood_enclosed_tiles(loop_finder: &LoopFinder) -> BTreeSet<(usize, usize)> {
+    let map = loop_finder.map;
+
+    // Each tile becomes 3x3 cells, so that pipes running side by side leave a gap between
+    // them, and a ring of empty cells goes around the map. Flood the cells from the outside.
+    let (rows, cols) = map.size;
+    let (height, width) = (3 * rows + 2, 3 * cols + 2);
+    let center = |row: usize, col: usize| (3 * row + 2, 3 * col + 2);
+
+    let mut blocked = vec![vec![false; width]; height];
+    for (co, tile) in loop_finder.loop_coordinates.iter() {
+        let (x, y) = center(rows - co.lattitude - 1, co.longitude);
+        blocked[x][y] = true;
+        for dir in tile.connections() {
+            match dir {
+                Direction::East => blocked[x][y + 1] = true,
+                Direction::North => blocked[x - 1][y] = true,
+                Direction::West => blocked[x][y - 1] = true,
+                Direction::South => blocked[x + 1][y] = true,
+            }
+        }
+    }
+
+    let mut outside = vec![vec![false; width]; height];
+    outside[0][0] = true;
+    let mut queue = VecDeque::from([(0usize, 0usize)]);
+    while let Some((x, y)) = queue.pop_front() {
+        let neighbours = [
+            (x.checked_sub(1), Some(y)),
+            (Some(x + 1), Some(y)),
+            (Some(x), y.checked_sub(1)),
+            (Some(x), Some(y + 1)),
+        ];
+        for (nx, ny) in neighbours {
+            if let (Some(nx), Some(ny)) = (nx, ny) {
+                if nx < height && ny < width && !blocked[nx][ny] && !outside[nx][ny] {
+                    outside[nx][ny] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+
+    let mut result = BTreeSet::new();
+    for row in 0..rows {
+        for col in 0..cols {
+            let (x, y) = center(row, col);
+            if !blocked[x][y] && !outside[x][y] {
+                result.insert((row, col));
+            }
+        }
+    }
+
+    result
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::d10::enclosed_area;
+    use crate::d10::enclosed_tiles;
     use crate::d10::run_part_1;
     use crate::d10::run_part_2;
+    use crate::d10::Map;
+    use crate::d10::RenderOptions;
     use std::fs::read_to_string;
 
     #[test]
@@ -359,4 +541,72 @@ mod tests {
         let input = read_to_string("src/d10/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 459);
     }
+
+    #[test]
+    fn starting_tile_is_inferred() {
+        // S in a corner, where it can only be an F
+        let input = "S-7\n|.|\nL-J".to_string();
+        assert_eq!(run_part_1(input.clone()).unwrap(), 4);
+        assert_eq!(run_part_2(input).unwrap(), 1);
+
+        // S with three connected neighbours
+        let input = "F-7.\nS-J.\nL-7.\n..L.".to_string();
+        let error = run_part_1(input).unwrap_err().to_string();
+        assert!(error.contains("ambiguous"));
+
+        // S with a single connected neighbour
+        let input = "S-7\n..|\n..J".to_string();
+        assert!(run_part_1(input).is_err());
+    }
+
+    #[test]
+    fn area_engines_agree() {
+        for file in ["test", "test2", "prod"] {
+            let input =
+                read_to_string(format!("src/d10/{}.txt", file)).expect("could not read file");
+            let expected = run_part_2(input.clone()).unwrap();
+            assert_eq!(enclosed_area(input.clone()).unwrap(), expected);
+            assert_eq!(enclosed_tiles(input).unwrap().len(), expected);
+        }
+
+        let input = "S-7\n|.|\nL-J".to_string();
+        assert_eq!(
+            enclosed_tiles(input)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [(1, 1)]
+        );
+
+        // a loop on lines longer than the first one
+        assert!(enclosed_tiles(".\nS-7\nL-J".to_string()).is_err());
+        assert!(enclosed_area(".\nS-7\nL-J".to_string()).is_err());
+    }
+
+    #[test]
+    fn box_drawing_round_trip() {
+        let input = read_to_string("src/d10/test.txt").expect("could not read file");
+        let map = Map::try_from(input.trim()).unwrap();
+
+        let drawn = map.to_string();
+        assert_eq!(drawn.lines().next(), Some("┐─┌┐─"));
+        assert_eq!(Map::try_from(drawn.as_str()).unwrap(), map);
+        assert_eq!(run_part_1(drawn).unwrap(), 8);
+
+        let options = RenderOptions {
+            main_loop: true,
+            enclosed: true,
+            start: true,
+        };
+        let rendered = map.render(&options).unwrap();
+        assert!(rendered.contains("\x1b[1;33mS\x1b[0m"));
+        assert!(rendered.contains("\x1b[32m┌\x1b[0m"));
+        assert_eq!(
+            map.render(&RenderOptions::default()).unwrap(),
+            map.to_string()
+        );
+
+        assert!(Map::try_from("").is_err());
+        assert!(Map::try_from("S-7\n|").is_err());
+    }
 }
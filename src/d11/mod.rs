@@ -1,31 +1,99 @@
-use std::collections::BTreeSet;
+use std::{collections::BTreeMap, fmt::Display};
 
 use anyhow::{Context, Result};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Coordinate {
-    lattitude: usize,
-    longitude: usize,
+pub struct Coordinate {
+    pub lattitude: u128,
+    pub longitude: u128,
 }
 
-impl Coordinate {
-    fn distance(&self, other: &Self) -> usize {
-        self.lattitude.abs_diff(other.lattitude) + self.longitude.abs_diff(other.longitude)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Metric {
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
+// Exact distance: a whole number for the Manhattan and Chebyshev metrics, and the square root
+// of one for the Euclidean metric, which keeps the order without any rounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Distance {
+    Whole(u128),
+    SquareRoot(u128),
+}
+
+impl Distance {
+    // rounded down
+    pub fn floor(&self) -> u128 {
+        match self {
+            Distance::Whole(n) => *n,
+            Distance::SquareRoot(n) => n.isqrt(),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Distance::Whole(n) => *n as f64,
+            Distance::SquareRoot(n) => (*n as f64).sqrt(),
+        }
+    }
+}
+
+impl Display for Distance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Distance::Whole(n) => write!(f, "{}", n),
+            Distance::SquareRoot(n) if n.isqrt() * n.isqrt() == *n => write!(f, "{}", n.isqrt()),
+            Distance::SquareRoot(n) => write!(f, "√{}", n),
+        }
+    }
+}
+
+impl Metric {
+    pub fn distance(&self, a: &Coordinate, b: &Coordinate) -> Result<Distance> {
+        let lattitude = a.lattitude.abs_diff(b.lattitude);
+        let longitude = a.longitude.abs_diff(b.longitude);
+
+        match self {
+            Metric::Manhattan => lattitude.checked_add(longitude).map(Distance::Whole),
+            Metric::Chebyshev => Some(Distance::Whole(lattitude.max(longitude))),
+            Metric::Euclidean => lattitude
+                .checked_mul(lattitude)
+                .zip(longitude.checked_mul(longitude))
+                .and_then(|(lat, long)| lat.checked_add(long))
+                .map(Distance::SquareRoot),
+        }
+        .context("Distance is too large")
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Space {
+pub struct Space {
     galaxies: Vec<Coordinate>,
 }
 
-impl TryFrom<(&str, usize)> for Space {
+// For each line, how many empty lines come before it
+fn empty_lines_before(occupied: &[bool]) -> Vec<u128> {
+    let mut result = Vec::with_capacity(occupied.len());
+    let mut empty = 0;
+    for occ in occupied.iter() {
+        result.push(empty);
+        if !occ {
+            empty += 1;
+        }
+    }
+    result
+}
+
+// Each empty line or column becomes `expansion` of them, so 1 leaves the space as it is
+// and 0 removes them
+impl TryFrom<(&str, u64)> for Space {
     type Error = anyhow::Error;
 
-    fn try_from((value, exp_factor): (&str, usize)) -> Result<Self> {
+    fn try_from((value, expansion): (&str, u64)) -> Result<Self> {
         let mut galaxies = Vec::new();
 
-        let no_of_lines = value.lines().fold(0, |acc, _| acc + 1);
         let no_of_cols = value
             .lines()
             .next()
@@ -33,95 +101,163 @@ impl TryFrom<(&str, usize)> for Space {
             .chars()
             .fold(0, |acc, _| acc + 1);
 
+        let mut lines_with_galaxies = Vec::new();
+        let mut cols_with_galaxies = vec![false; no_of_cols];
+
         for (i, line) in value.lines().enumerate() {
+            lines_with_galaxies.push(false);
             for (j, char) in line.chars().enumerate() {
                 if char == '#' {
-                    galaxies.push(Coordinate {
-                        lattitude: i,
-                        longitude: j,
-                    });
+                    lines_with_galaxies[i] = true;
+                    *cols_with_galaxies
+                        .get_mut(j)
+                        .context("Lines of different sizes")? = true;
+                    galaxies.push((i, j));
                 }
             }
         }
 
-        let lines_with_galaxies = galaxies.iter().fold(BTreeSet::new(), |mut acc, v| {
-            acc.insert(v.lattitude);
-            acc
-        });
+        let empty_lines = empty_lines_before(&lines_with_galaxies);
+        let empty_cols = empty_lines_before(&cols_with_galaxies);
+        let expand = |position: usize, empty: u128| {
+            empty
+                .checked_mul(expansion as u128)
+                .and_then(|e| e.checked_add(position as u128 - empty))
+                .context("Space is too large")
+        };
 
-        let cols_with_galaxies = galaxies.iter().fold(BTreeSet::new(), |mut acc, v| {
-            acc.insert(v.longitude);
-            acc
-        });
+        let galaxies = galaxies
+            .into_iter()
+            .map(|(i, j)| {
+                Ok(Coordinate {
+                    lattitude: expand(i, empty_lines[i])?,
+                    longitude: expand(j, empty_cols[j])?,
+                })
+            })
+            .collect::<Result<Vec<Coordinate>>>()?;
 
-        let mut lines_without_galaxies = Vec::new();
-        for i in 0..no_of_lines {
-            if !lines_with_galaxies.contains(&i) {
-                lines_without_galaxies.push(i);
-            }
-        }
+        Ok(Self { galaxies })
+    }
+}
 
-        let mut cols_without_galaxies = Vec::new();
-        for i in 0..no_of_cols {
-            if !cols_with_galaxies.contains(&i) {
-                cols_without_galaxies.push(i);
-            }
-        }
+// Sum of |a - b| over every pair of values: once sorted, the k-th value is
+// larger than the k values before it
+fn sum_of_differences(mut values: Vec<u128>) -> Result<u128> {
+    values.sort_unstable();
 
-        for i in lines_without_galaxies.iter().rev() {
-            galaxies
-                .iter_mut()
-                .filter(|gal| gal.lattitude > *i)
-                .for_each(|gal| {
-                    gal.lattitude += exp_factor;
-                });
-        }
+    let mut result: u128 = 0;
+    let mut sum_before: u128 = 0;
+    for (k, v) in values.iter().enumerate() {
+        let term = v
+            .checked_mul(k as u128)
+            .context("Sum of distances is too large")?
+            - sum_before;
+        result = result
+            .checked_add(term)
+            .context("Sum of distances is too large")?;
+        sum_before = sum_before
+            .checked_add(*v)
+            .context("Sum of distances is too large")?;
+    }
 
-        for i in cols_without_galaxies.iter().rev() {
-            galaxies
-                .iter_mut()
-                .filter(|gal| gal.longitude > *i)
-                .for_each(|gal| {
-                    gal.longitude += exp_factor;
-                });
-        }
+    Ok(result)
+}
 
-        Ok(Self { galaxies })
+impl Space {
+    pub fn galaxies(&self) -> &[Coordinate] {
+        &self.galaxies
     }
-}
 
-pub fn run_part_1(input: String) -> Result<usize> {
-    let space = Space::try_from((input.trim(), 1))?;
+    // Every other galaxy as (distance, index), closest first
+    pub fn neighbours(&self, galaxy: usize, metric: Metric) -> Result<Vec<(Distance, usize)>> {
+        let from = self.galaxies.get(galaxy).context("Unknown galaxy")?;
 
-    let mut result = 0;
+        let mut result = self
+            .galaxies
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != galaxy)
+            .map(|(i, to)| Ok((metric.distance(from, to)?, i)))
+            .collect::<Result<Vec<(Distance, usize)>>>()?;
+        result.sort_unstable();
 
-    for i in 0..space.galaxies.len() {
-        for j in 0..i {
-            result += space.galaxies[i].distance(&space.galaxies[j]);
-        }
+        Ok(result)
     }
 
-    Ok(result)
-}
+    pub fn nearest(&self, galaxy: usize, metric: Metric) -> Result<Option<(Distance, usize)>> {
+        Ok(self.neighbours(galaxy, metric)?.first().copied())
+    }
 
-pub fn run_part_2(input: String) -> Result<usize> {
-    let space = Space::try_from((input.trim(), 999_999))?;
+    // ties go to the lowest index, as for the nearest one
+    pub fn farthest(&self, galaxy: usize, metric: Metric) -> Result<Option<(Distance, usize)>> {
+        let neighbours = self.neighbours(galaxy, metric)?;
+
+        Ok(neighbours.last().and_then(|(max, _)| {
+            neighbours
+                .iter()
+                .find(|(distance, _)| distance == max)
+                .copied()
+        }))
+    }
+
+    pub fn k_nearest(
+        &self,
+        galaxy: usize,
+        k: usize,
+        metric: Metric,
+    ) -> Result<Vec<(Distance, usize)>> {
+        let mut neighbours = self.neighbours(galaxy, metric)?;
+        neighbours.truncate(k);
 
-    let mut result = 0;
+        Ok(neighbours)
+    }
+
+    // How many pairs of galaxies lie at each distance
+    pub fn histogram(&self, metric: Metric) -> Result<BTreeMap<Distance, usize>> {
+        let mut result = BTreeMap::new();
 
-    for i in 0..space.galaxies.len() {
-        for j in 0..i {
-            result += space.galaxies[i].distance(&space.galaxies[j]);
+        for (i, a) in self.galaxies.iter().enumerate() {
+            for b in self.galaxies.iter().take(i) {
+                *result.entry(metric.distance(a, b)?).or_insert(0) += 1;
+            }
         }
+
+        Ok(result)
     }
 
-    Ok(result)
+    fn sum_of_distances(&self) -> Result<u128> {
+        let lattitudes = sum_of_differences(self.galaxies.iter().map(|g| g.lattitude).collect())?;
+        let longitudes = sum_of_differences(self.galaxies.iter().map(|g| g.longitude).collect())?;
+
+        lattitudes
+            .checked_add(longitudes)
+            .context("Sum of distances is too large")
+    }
+}
+
+// Sum of the distances between every pair of galaxies, after expanding the empty space
+pub fn sum_of_distances(input: &str, expansion: u64) -> Result<u128> {
+    let space = Space::try_from((input.trim(), expansion))?;
+
+    space.sum_of_distances()
+}
+
+pub fn run_part_1(input: String) -> Result<usize> {
+    usize::try_from(sum_of_distances(&input, 2)?).context("Result too large")
+}
+
+pub fn run_part_2(input: String) -> Result<usize> {
+    usize::try_from(sum_of_distances(&input, 1_000_000)?).context("Result too large")
 }
 
 #[cfg(test)]
 mod tests {
     use crate::d11::run_part_1;
     use crate::d11::run_part_2;
+    use crate::d11::sum_of_distances;
+    use crate::d11::Distance;
+    use crate::d11::Metric;
+    use crate::d11::Space;
     use std::fs::read_to_string;
 
     #[test]
@@ -147,4 +283,90 @@ mod tests {
         let input = read_to_string("src/d11/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 458191688761);
     }
+
+    #[test]
+    fn any_expansion() {
+        let input = read_to_string("src/d11/test.txt").expect("could not read file");
+
+        for expansion in [0, 1, 2, 10, 100] {
+            let space = Space::try_from((input.trim(), expansion)).unwrap();
+            let mut expected = 0;
+            for i in 0..space.galaxies.len() {
+                for j in 0..i {
+                    expected += Metric::Manhattan
+                        .distance(&space.galaxies[i], &space.galaxies[j])
+                        .unwrap()
+                        .floor();
+                }
+            }
+            assert_eq!(sum_of_distances(&input, expansion).unwrap(), expected);
+        }
+
+        assert_eq!(sum_of_distances(&input, 10).unwrap(), 1030);
+        assert!(sum_of_distances(&input, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn metrics_and_neighbours() {
+        let input = read_to_string("src/d11/test.txt").expect("could not read file");
+        let space = Space::try_from((input.trim(), 2)).unwrap();
+        assert_eq!(space.galaxies().len(), 9);
+
+        let whole = Distance::Whole;
+        assert_eq!(
+            space.nearest(0, Metric::Manhattan).unwrap(),
+            Some((whole(6), 1))
+        );
+        assert_eq!(
+            space.farthest(0, Metric::Manhattan).unwrap(),
+            Some((whole(15), 5))
+        );
+        assert_eq!(
+            space.nearest(4, Metric::Chebyshev).unwrap(),
+            Some((whole(4), 2))
+        );
+        assert_eq!(
+            space.farthest(4, Metric::Euclidean).unwrap(),
+            Some((Distance::SquareRoot(122), 5))
+        );
+        assert_eq!(
+            space.k_nearest(4, 3, Metric::Manhattan).unwrap(),
+            [(whole(5), 2), (whole(6), 7), (whole(8), 3)]
+        );
+        assert_eq!(space.k_nearest(0, 20, Metric::Chebyshev).unwrap().len(), 8);
+        assert!(space.nearest(9, Metric::Manhattan).is_err());
+
+        let histogram = space.histogram(Metric::Manhattan).unwrap();
+        assert_eq!(histogram.values().sum::<usize>(), 36);
+        assert_eq!(histogram.first_key_value(), Some((&whole(5), &4)));
+        assert_eq!(histogram.last_key_value(), Some((&whole(19), &1)));
+        assert_eq!(
+            histogram
+                .iter()
+                .map(|(d, n)| d.floor() * *n as u128)
+                .sum::<u128>(),
+            374
+        );
+        assert_eq!(
+            space.histogram(Metric::Chebyshev).unwrap().get(&whole(4)),
+            Some(&5)
+        );
+
+        // 3-4-5 triangle, and a diagonal step
+        let space = Space::try_from(("#....\n.....\n.....\n....#", 1)).unwrap();
+        let (distance, _) = space.nearest(0, Metric::Euclidean).unwrap().unwrap();
+        assert_eq!(distance.to_string(), "5");
+        assert_eq!(distance.floor(), 5);
+        assert_eq!(distance.to_f64(), 5.0);
+
+        let space = Space::try_from(("#.\n.#", 1)).unwrap();
+        let (distance, _) = space.nearest(0, Metric::Euclidean).unwrap().unwrap();
+        assert_eq!(distance.to_string(), "√2");
+        assert_eq!(distance.floor(), 1);
+        assert!((distance.to_f64() - 2f64.sqrt()).abs() < 1e-12);
+
+        let huge = Space::try_from((input.trim(), u64::MAX)).unwrap();
+        assert!(huge.histogram(Metric::Manhattan).is_ok());
+        assert!(huge.histogram(Metric::Euclidean).is_err());
+    }
 }
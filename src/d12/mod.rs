@@ -1,9 +1,11 @@
-use std::collections::BTreeMap;
+use std::fmt::Display;
 
 use anyhow::{Context, Result};
 
+pub mod nonogram;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Spring {
+pub enum Spring {
     Operational,
     Damaged,
     Unknown,
@@ -17,16 +19,31 @@ impl TryFrom<char> for Spring {
             '.' => Ok(Self::Operational),
             '#' => Ok(Self::Damaged),
             '?' => Ok(Self::Unknown),
-            _ => unreachable!(),
+            _ => Err(anyhow::anyhow!("Invalid spring")),
+        }
+    }
+}
+
+impl Display for Spring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Operational => write!(f, "."),
+            Self::Damaged => write!(f, "#"),
+            Self::Unknown => write!(f, "?"),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Arrangement {
+pub struct Arrangement {
     springs: Vec<Spring>,
     sequences: Vec<usize>,
-    cache: BTreeMap<(usize, usize), usize>,
+    // for each spring, how many springs from it on could all be damaged
+    runs: Vec<usize>,
+    // number of solutions starting from each (spring, sequence), flattened row by row with
+    // sequences.len() + 1 columns, and springs.len() + 2 rows as a sequence can end on the last
+    // spring and skip the one after it
+    solutions: Vec<u128>,
 }
 
 impl TryFrom<&str> for Arrangement {
@@ -42,152 +59,354 @@ impl TryFrom<&str> for Arrangement {
             .split(",")
             .map(|s| s.parse::<usize>().context("Not a valid number"))
             .collect::<Result<Vec<usize>>>()?;
-        let cache = BTreeMap::new();
 
-        Ok(Self {
-            springs,
-            sequences,
-            cache,
-        })
+        Self::new(springs, sequences)
     }
 }
 
 impl Arrangement {
+    fn new(springs: Vec<Spring>, sequences: Vec<usize>) -> Result<Self> {
+        let mut runs = vec![0; springs.len() + 1];
+        for (i, spring) in springs.iter().enumerate().rev() {
+            if *spring != Spring::Operational {
+                runs[i] = runs[i + 1] + 1;
+            }
+        }
+
+        let mut result = Self {
+            springs,
+            sequences,
+            runs,
+            solutions: Vec::new(),
+        };
+        result.calculate_solutions()?;
+
+        Ok(result)
+    }
+
+    // Repeats the springs k times, separated by an unknown spring, and the sequences k times
+    pub fn unfold(&self, k: usize) -> Result<Self> {
+        let mut springs = Vec::new();
+        for i in 0..k {
+            if i > 0 {
+                springs.push(Spring::Unknown);
+            }
+            springs.extend(self.springs.iter().cloned());
+        }
+        let sequences = self.sequences.repeat(k);
+
+        Self::new(springs, sequences)
+    }
+
+    fn index(&self, starting_from_spring: usize, starting_from_sequence: usize) -> usize {
+        starting_from_spring * (self.sequences.len() + 1) + starting_from_sequence
+    }
+
     fn find_possible_solutions(
-        &mut self,
+        &self,
         starting_from_spring: usize,
         starting_from_sequence: usize,
-    ) -> usize {
-        // at some points the recursion will get back to a state that was already calculated, so a
-        // cache is helpful here
-        if let Some(cache_hit) = self
-            .cache
-            .get(&(starting_from_spring, starting_from_sequence))
+    ) -> u128 {
+        self.solutions[self.index(starting_from_spring, starting_from_sequence)]
+    }
+
+    // whether the current sequence can start at this spring: the next n springs must all be
+    // able to be damaged, and the spring right after the end must not be a damaged one
+    fn fits_damaged(&self, starting_from_spring: usize, starting_from_sequence: usize) -> bool {
+        let current_sequence = self.sequences[starting_from_sequence];
+        self.runs[starting_from_spring] >= current_sequence
+            && self.springs.get(starting_from_spring + current_sequence) != Some(&Spring::Damaged)
+    }
+
+    // Fills the table from the last spring back to the first one, as each state only depends on
+    // states further along the springs
+    fn calculate_solutions(&mut self) -> Result<()> {
+        let springs = self.springs.len();
+        let sequences = self.sequences.len();
+        self.solutions = vec![0; (springs + 2) * (sequences + 1)];
+
+        for starting_from_spring in (0..springs + 2).rev() {
+            // it ran out of sequences to be evaluated: a solution if there are no damaged
+            // springs remaining
+            let no_damaged_remaining = starting_from_spring >= springs
+                || (self.find_possible_solutions(starting_from_spring + 1, sequences) == 1
+                    && self.springs[starting_from_spring] != Spring::Damaged);
+            let index = self.index(starting_from_spring, sequences);
+            self.solutions[index] = no_damaged_remaining as u128;
+
+            // a sequence remains to be evaluated but it ran out of springs: not a solution
+            if starting_from_spring >= springs {
+                continue;
+            }
+
+            for starting_from_sequence in 0..sequences {
+                let spring = &self.springs[starting_from_spring];
+
+                // moves to the next spring, remains in the same sequence
+                let as_operational = match spring {
+                    Spring::Damaged => 0,
+                    _ => self
+                        .find_possible_solutions(starting_from_spring + 1, starting_from_sequence),
+                };
+
+                // moves to the next n springs and to the next sequence
+                let as_damaged = match spring {
+                    Spring::Operational => 0,
+                    _ if self.fits_damaged(starting_from_spring, starting_from_sequence) => self
+                        .find_possible_solutions(
+                            starting_from_spring + self.sequences[starting_from_sequence] + 1,
+                            starting_from_sequence + 1,
+                        ),
+                    _ => 0,
+                };
+
+                let index = self.index(starting_from_spring, starting_from_sequence);
+                self.solutions[index] = as_operational
+                    .checked_add(as_damaged)
+                    .context("Too many solutions")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn count_solutions(&self) -> u128 {
+        self.find_possible_solutions(0, 0)
+    }
+
+    // The next states from a position, as the springs they resolve to. The operational branch
+    // comes first, and only the branches leading to at least one solution are kept
+    fn branches(
+        &self,
+        starting_from_spring: usize,
+        starting_from_sequence: usize,
+    ) -> Vec<(usize, usize, Vec<Spring>)> {
+        let mut result = Vec::new();
+        let spring = &self.springs[starting_from_spring];
+
+        if *spring != Spring::Damaged
+            && self.find_possible_solutions(starting_from_spring + 1, starting_from_sequence) > 0
         {
-            return *cache_hit;
+            result.push((
+                starting_from_spring + 1,
+                starting_from_sequence,
+                vec![Spring::Operational],
+            ));
         }
 
-        // end of recursion: at some point, one of these trivial cases will happen:
-        // - a sequence remains to be evaluated but it ran out of springs: not a solution
-        // - a damaged spring is still awaiting a sequence to match but it ran out of sequences: not a solution
-        // - it ran out of sequences to be evaluated and there are no damaged springs remaining: A SOLUTION
-        if starting_from_sequence >= self.sequences.len() {
-            if starting_from_spring >= self.springs.len()
-                || !self.springs[starting_from_spring..].contains(&Spring::Damaged)
-            {
-                return 1;
-            } else {
-                return 0;
-            };
+        if *spring != Spring::Operational
+            && self.fits_damaged(starting_from_spring, starting_from_sequence)
+        {
+            let current_sequence = self.sequences[starting_from_sequence];
+            let next_spring = starting_from_spring + current_sequence + 1;
+            if self.find_possible_solutions(next_spring, starting_from_sequence + 1) > 0 {
+                let mut resolved = vec![Spring::Damaged; current_sequence];
+                if next_spring <= self.springs.len() {
+                    resolved.push(Spring::Operational);
+                }
+                result.push((next_spring, starting_from_sequence + 1, resolved));
+            }
         }
-        if starting_from_spring >= self.springs.len() {
-            return 0;
+
+        result
+    }
+
+    // Lazily goes through every solution, as rows without unknown springs
+    pub fn solutions(&self) -> Solutions<'_> {
+        let stack = match self.count_solutions() {
+            0 => Vec::new(),
+            _ => vec![(0, 0, Vec::new())],
+        };
+
+        Solutions {
+            arrangement: self,
+            stack,
         }
+    }
 
-        // running the function recursively depending on what is the spring at the starting_from_spring position
-        let next_run = match self.springs[starting_from_spring] {
-            Spring::Operational => {
-                // moves to the next spring, remains in the same sequence
-                self.find_possible_solutions(starting_from_spring + 1, starting_from_sequence)
-            }
-            Spring::Damaged => {
-                // this can be the start of a damaged sequence
-                let current_sequence = self.sequences[starting_from_sequence];
-                if starting_from_spring + current_sequence > self.springs.len()
-                    || self.springs[starting_from_spring..starting_from_spring + current_sequence]
-                        .contains(&Spring::Operational)
-                    || self.springs.get(starting_from_spring + current_sequence)
-                        == Some(&Spring::Damaged)
-                {
-                    // evaluates the next n springs that can be a damaged sequence
-                    // if there are any operational springs in this interval, or if the spring
-                    // right after the end is a damaged one, this can't be the start of the
-                    // sequence with that length
-                    0
-                } else {
-                    // moves to the next n springs and to the next sequence
-                    self.find_possible_solutions(
-                        starting_from_spring + current_sequence + 1,
-                        starting_from_sequence + 1,
-                    )
+    // The solution at this position in the order of `solutions`
+    fn nth_solution(&self, mut rank: u128) -> Option<Vec<Spring>> {
+        if rank >= self.count_solutions() {
+            return None;
+        }
+
+        let mut result = Vec::new();
+        let (mut spring, mut sequence) = (0, 0);
+        while sequence < self.sequences.len() {
+            for (next_spring, next_sequence, resolved) in self.branches(spring, sequence) {
+                let count = self.find_possible_solutions(next_spring, next_sequence);
+                if rank < count {
+                    result.extend(resolved);
+                    (spring, sequence) = (next_spring, next_sequence);
+                    break;
                 }
+                rank -= count;
             }
-            Spring::Unknown => {
-                // this can be the start of a damaged sequence or an operational spring
-                let current_sequence = self.sequences[starting_from_sequence];
-                if starting_from_spring + current_sequence > self.springs.len()
-                    || self.springs[starting_from_spring..starting_from_spring + current_sequence]
-                        .contains(&Spring::Operational)
-                    || self.springs.get(starting_from_spring + current_sequence)
-                        == Some(&Spring::Damaged)
-                {
-                    // evaluates the next n springs that can be a damaged sequence
-                    // if there are any operational springs in this interval, or if the spring
-                    // right after the end is a damaged one, the current spring can only be
-                    // an operational one
-                    self.find_possible_solutions(starting_from_spring + 1, starting_from_sequence)
-                } else {
-                    // else it can be both, so it calculates the possible solutions if this is an
-                    // operational spring plus if this is a damaged spring
-                    self.find_possible_solutions(
-                        starting_from_spring + current_sequence + 1,
-                        starting_from_sequence + 1,
-                    ) + self
-                        .find_possible_solutions(starting_from_spring + 1, starting_from_sequence)
-                }
+        }
+        result.resize(self.springs.len(), Spring::Operational);
+
+        Some(result)
+    }
+
+    // Uniformly random solution. next_random must be uniform over the whole u128 range: a
+    // generator of fewer bits only ever picks from the first solutions
+    pub fn random_solution(&self, mut next_random: impl FnMut() -> u128) -> Option<Vec<Spring>> {
+        let count = self.count_solutions();
+        if count == 0 {
+            return None;
+        }
+
+        // rejects the numbers past the last multiple of count, so that each rank is as likely
+        let limit = u128::MAX - u128::MAX % count;
+        let rank = loop {
+            let random = next_random();
+            if random < limit {
+                break random % count;
             }
         };
 
-        self.cache
-            .insert((starting_from_spring, starting_from_sequence), next_run);
-        return next_run;
+        self.nth_solution(rank)
+    }
+
+    // The number of solutions, and for each spring in how many of them it is damaged.
+    // Walks the states forward counting the ways to reach each of them, so that a damaged
+    // sequence placed at a state appears in (ways to reach it) * (solutions after it) solutions.
+    // Only states leading to a solution are walked, so none of these exceed the total
+    fn damaged_counts(&self) -> Option<(u128, Vec<u128>)> {
+        let total = self.count_solutions();
+        if total == 0 {
+            return None;
+        }
+
+        let mut ways = vec![0u128; self.solutions.len()];
+        ways[0] = 1;
+        let mut damaged = vec![0u128; self.springs.len()];
+
+        for spring in 0..self.springs.len() {
+            for sequence in 0..self.sequences.len() {
+                let reaching = ways[self.index(spring, sequence)];
+                if reaching == 0 {
+                    continue;
+                }
+
+                for (next_spring, next_sequence, resolved) in self.branches(spring, sequence) {
+                    ways[self.index(next_spring, next_sequence)] += reaching;
+                    if next_sequence > sequence {
+                        let solutions =
+                            reaching * self.find_possible_solutions(next_spring, next_sequence);
+                        for (i, resolved) in resolved.iter().enumerate() {
+                            if *resolved == Spring::Damaged {
+                                damaged[spring + i] += solutions;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Some((total, damaged))
+    }
+
+    // For each unknown spring, the probability that it is damaged across all solutions
+    pub fn damaged_probabilities(&self) -> Option<Vec<(usize, f64)>> {
+        let (total, damaged) = self.damaged_counts()?;
+
+        Some(
+            self.springs
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| **s == Spring::Unknown)
+                .map(|(i, _)| (i, damaged[i] as f64 / total as f64))
+                .collect(),
+        )
     }
 }
 
-pub fn run_part_1(input: String) -> Result<usize> {
-    let mut arrangements = input
-        .trim()
-        .lines()
-        .map(|arr| Arrangement::try_from(arr))
-        .collect::<Result<Vec<Arrangement>>>()?;
+pub struct Solutions<'a> {
+    arrangement: &'a Arrangement,
+    // the positions still to explore, with the springs resolved on the way there
+    stack: Vec<(usize, usize, Vec<Spring>)>,
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Vec<Spring>;
 
-    let result = arrangements
-        .iter_mut()
-        .map(|arr| arr.find_possible_solutions(0, 0))
-        .sum();
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (spring, sequence, resolved) = self.stack.pop()?;
+
+            if sequence >= self.arrangement.sequences.len() {
+                let mut result = resolved;
+                result.resize(self.arrangement.springs.len(), Spring::Operational);
+                return Some(result);
+            }
 
-    Ok(result)
+            // pushed in reverse, so that the operational branch is explored first
+            for (next_spring, next_sequence, next) in self
+                .arrangement
+                .branches(spring, sequence)
+                .into_iter()
+                .rev()
+            {
+                let mut next_resolved = resolved.clone();
+                next_resolved.extend(next);
+                self.stack.push((next_spring, next_sequence, next_resolved));
+            }
+        }
+    }
 }
 
-pub fn run_part_2(input: String) -> Result<usize> {
-    let mut arrangements = input
+// Sum of the solutions of every row, unfolded k times
+pub fn total_solutions(input: &str, k: usize) -> Result<u128> {
+    input
         .trim()
         .lines()
-        .map(|line| -> Result<String, _> {
-            let (springs, sequences) = line.split_once(" ").context("Space not found")?;
-            let five_springs = (0..5).map(|_| springs).collect::<Vec<&str>>().join("?");
-            let five_sequences = (0..5).map(|_| sequences).collect::<Vec<&str>>().join(",");
-            Ok(format!("{} {}", five_springs, five_sequences))
+        .map(|arr| Arrangement::try_from(arr)?.unfold(k))
+        .try_fold(0u128, |acc, arr| {
+            acc.checked_add(arr?.count_solutions())
+                .context("Too many solutions")
         })
-        .collect::<Result<Vec<String>>>()?
-        .iter()
-        .map(|arr| Arrangement::try_from(&arr[..]))
-        .collect::<Result<Vec<Arrangement>>>()?;
+}
 
-    let result = arrangements
-        .iter_mut()
-        .map(|arr| arr.find_possible_solutions(0, 0))
-        .sum();
+pub fn run_part_1(input: String) -> Result<usize> {
+    usize::try_from(total_solutions(&input, 1)?).context("Result too large")
+}
 
-    Ok(result)
+pub fn run_part_2(input: String) -> Result<usize> {
+    usize::try_from(total_solutions(&input, 5)?).context("Result too large")
 }
 
 #[cfg(test)]
 mod tests {
     use crate::d12::run_part_1;
     use crate::d12::run_part_2;
+    use crate::d12::total_solutions;
+    use crate::d12::Arrangement;
+    use crate::d12::Spring;
+    use crate::test_util::Lcg;
     use std::fs::read_to_string;
 
+    fn to_string(springs: &[Spring]) -> String {
+        springs.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn is_solution(line: &str, solution: &str) -> bool {
+        let (springs, sequences) = line.split_once(" ").unwrap();
+        let groups = solution
+            .split(".")
+            .filter(|g| !g.is_empty())
+            .map(|g| g.len().to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+
+        springs.len() == solution.len()
+            && springs
+                .chars()
+                .zip(solution.chars())
+                .all(|(s, r)| s == '?' || s == r)
+            && groups == sequences
+    }
+
     #[test]
     fn part_1_test() {
         let input = read_to_string("src/d12/test.txt").expect("could not read file");
@@ -211,4 +430,65 @@ mod tests {
         let input = read_to_string("src/d12/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 28606137449920);
     }
+
+    #[test]
+    fn enumerate_and_sample() {
+        let input = read_to_string("src/d12/test.txt").expect("could not read file");
+
+        for line in input.trim().lines() {
+            let arrangement = Arrangement::try_from(line).unwrap();
+            let count = arrangement.count_solutions();
+            let solutions = arrangement
+                .solutions()
+                .map(|s| to_string(&s))
+                .collect::<Vec<String>>();
+
+            assert_eq!(solutions.len() as u128, count);
+            assert!(solutions.windows(2).all(|w| w[0] != w[1]));
+            assert!(solutions.iter().all(|s| is_solution(line, s)));
+
+            let probabilities = arrangement.damaged_probabilities().unwrap();
+            for (i, probability) in probabilities {
+                let damaged = solutions.iter().filter(|s| s.as_bytes()[i] == b'#').count();
+                assert!((probability - damaged as f64 / count as f64).abs() < 1e-9);
+            }
+        }
+
+        let arrangement = Arrangement::try_from("?###???????? 3,2,1").unwrap();
+        let mut lcg = Lcg::new(12345);
+        let mut seen = std::collections::BTreeMap::new();
+        for _ in 0..1000 {
+            let solution = arrangement.random_solution(|| lcg.next_u128()).unwrap();
+            *seen.entry(to_string(&solution)).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), 10);
+        assert!(seen.keys().all(|s| is_solution("?###???????? 3,2,1", s)));
+        assert!(seen.values().all(|n| *n > 50));
+
+        assert!(Arrangement::try_from("x 1").is_err());
+
+        let impossible = Arrangement::try_from("#.# 3").unwrap();
+        assert_eq!(impossible.solutions().next(), None);
+        assert_eq!(impossible.random_solution(|| 0), None);
+        assert_eq!(impossible.damaged_probabilities(), None);
+    }
+
+    #[test]
+    fn unfold_any_number_of_times() {
+        let input = read_to_string("src/d12/test.txt").expect("could not read file");
+        assert_eq!(total_solutions(&input, 1).unwrap(), 21);
+        assert_eq!(total_solutions(&input, 5).unwrap(), 525152);
+        assert_eq!(total_solutions(&input, 0).unwrap(), 6);
+
+        let arrangement = Arrangement::try_from("?###???????? 3,2,1").unwrap();
+        assert_eq!(arrangement.unfold(5).unwrap().count_solutions(), 506250);
+
+        // k sequences of 1 among 3k - 1 unknown springs: C(2k, k) solutions
+        let arrangement = Arrangement::try_from("?? 1").unwrap();
+        assert_eq!(
+            arrangement.unfold(40).unwrap().count_solutions(),
+            107507208733336176461620
+        );
+        assert!(arrangement.unfold(100).is_err());
+    }
 }
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+
+use crate::d12::{Arrangement, Spring};
+
+// Clues for every row and column of a grid, where each filled cell is a damaged spring, so
+// each line is solved as one row of springs
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    NoSolution,
+    Unique(Vec<Vec<Spring>>),
+    // two of the solutions
+    Multiple(Vec<Vec<Spring>>, Vec<Vec<Spring>>),
+}
+
+fn parse_clues(value: &str) -> Result<Vec<Vec<usize>>> {
+    value
+        .lines()
+        .map(|line| {
+            line.split(",")
+                .map(|s| s.trim().parse::<usize>().context("Not a valid number"))
+                // an empty line is written as 0
+                .filter(|s| !matches!(s, Ok(0)))
+                .collect::<Result<Vec<usize>>>()
+        })
+        .collect()
+}
+
+// row clues, an empty line, then column clues, one line of comma separated lengths each
+impl TryFrom<&str> for Nonogram {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        let (rows, cols) = value.split_once("\n\n").context("Column clues not found")?;
+
+        Ok(Self {
+            rows: parse_clues(rows)?,
+            cols: parse_clues(cols)?,
+        })
+    }
+}
+
+// Resolves the unknown cells of a line that are the same in all its solutions.
+// Returns false if the line has no solution
+fn solve_line(line: &mut [Spring], sequences: &[usize]) -> Result<bool> {
+    let arrangement = Arrangement::new(line.to_vec(), sequences.to_vec())?;
+
+    let Some((total, damaged)) = arrangement.damaged_counts() else {
+        return Ok(false);
+    };
+
+    for (spring, damaged) in line.iter_mut().zip(damaged) {
+        if *spring == Spring::Unknown {
+            if damaged == total {
+                *spring = Spring::Damaged;
+            } else if damaged == 0 {
+                *spring = Spring::Operational;
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+impl Nonogram {
+    // Solves rows and columns one at a time until none of them changes.
+    // Returns false on a contradiction
+    fn propagate(&self, grid: &mut [Vec<Spring>]) -> Result<bool> {
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (row, sequences) in grid.iter_mut().zip(self.rows.iter()) {
+                let before = row.clone();
+                if !solve_line(row, sequences)? {
+                    return Ok(false);
+                }
+                changed |= *row != before;
+            }
+
+            for (j, sequences) in self.cols.iter().enumerate() {
+                let mut col = grid
+                    .iter()
+                    .map(|row| row[j].clone())
+                    .collect::<Vec<Spring>>();
+                let before = col.clone();
+                if !solve_line(&mut col, sequences)? {
+                    return Ok(false);
+                }
+                if col != before {
+                    changed = true;
+                    for (row, spring) in grid.iter_mut().zip(col) {
+                        row[j] = spring;
+                    }
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Line solving first, guessing the first unknown cell when it stalls.
+    // Stops as soon as `limit` solutions were found
+    fn search(
+        &self,
+        mut grid: Vec<Vec<Spring>>,
+        limit: usize,
+        found: &mut Vec<Vec<Vec<Spring>>>,
+    ) -> Result<()> {
+        if !self.propagate(&mut grid)? {
+            return Ok(());
+        }
+
+        let unknown = grid.iter().enumerate().find_map(|(i, row)| {
+            row.iter()
+                .position(|s| *s == Spring::Unknown)
+                .map(|j| (i, j))
+        });
+
+        let Some((i, j)) = unknown else {
+            found.push(grid);
+            return Ok(());
+        };
+
+        for guess in [Spring::Damaged, Spring::Operational] {
+            if found.len() >= limit {
+                break;
+            }
+            let mut next = grid.clone();
+            next[i][j] = guess;
+            self.search(next, limit, found)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn solve(&self) -> Result<Outcome> {
+        let grid = vec![vec![Spring::Unknown; self.cols.len()]; self.rows.len()];
+        let mut found = Vec::new();
+        self.search(grid, 2, &mut found)?;
+
+        let mut found = found.into_iter();
+        Ok(match (found.next(), found.next()) {
+            (None, _) => Outcome::NoSolution,
+            (Some(solution), None) => Outcome::Unique(solution),
+            (Some(first), Some(second)) => Outcome::Multiple(first, second),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::d12::nonogram::Nonogram;
+    use crate::d12::nonogram::Outcome;
+    use crate::d12::Spring;
+
+    fn to_strings(grid: &[Vec<Spring>]) -> Vec<String> {
+        grid.iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn solve_nonograms() {
+        let nonogram = Nonogram::try_from("1,1,1\n5\n3\n1\n3\n\n2\n2,1\n5\n2,1\n2").unwrap();
+        let Outcome::Unique(solution) = nonogram.solve().unwrap() else {
+            panic!("expected a unique solution");
+        };
+        assert_eq!(
+            to_strings(&solution),
+            ["#.#.#", "#####", ".###.", "..#..", ".###."]
+        );
+
+        // either diagonal, which line solving alone can't decide
+        let Outcome::Multiple(first, second) =
+            Nonogram::try_from("1\n1\n\n1\n1").unwrap().solve().unwrap()
+        else {
+            panic!("expected multiple solutions");
+        };
+        assert_eq!(to_strings(&first), ["#.", ".#"]);
+        assert_eq!(to_strings(&second), [".#", "#."]);
+
+        assert_eq!(
+            Nonogram::try_from("2\n0\n\n1\n0").unwrap().solve().unwrap(),
+            Outcome::NoSolution
+        );
+        assert_eq!(
+            Nonogram::try_from("1\n1\n\n2\n0").unwrap().solve().unwrap(),
+            Outcome::Unique(vec![
+                vec![Spring::Damaged, Spring::Operational],
+                vec![Spring::Damaged, Spring::Operational],
+            ])
+        );
+        assert!(Nonogram::try_from("1\n1").is_err());
+    }
+}
@@ -18,176 +18,262 @@ impl TryFrom<char> for Pixel {
     }
 }
 
+// Pixels packed 64 to a word, the first one in the lowest bit, with the unused bits of the
+// last word left off
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct Line {
-    pixels: Vec<Pixel>,
+    len: usize,
+    bits: Vec<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Image {
+pub struct Image {
     rows: Vec<Line>,
     cols: Vec<Line>,
 }
 
+// Reflection line, between the line at this index and the one before it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Axis {
+    Horizontal(usize),
+    Vertical(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Symmetry {
+    // reflection across the line from the top left corner to the bottom right one
+    Diagonal,
+    // reflection across the line from the top right corner to the bottom left one
+    AntiDiagonal,
+    Rotation90,
+    Rotation180,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Region {
+    pub row: usize,
+    pub col: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Axis {
+    pub fn summary(&self) -> usize {
+        match self {
+            Axis::Horizontal(index) => 100 * index,
+            Axis::Vertical(index) => *index,
+        }
+    }
+}
+
 impl TryFrom<&str> for Image {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self> {
-        let rows = value
+        let pixels = value
             .lines()
             .map(|line| {
-                let pixels = line
-                    .chars()
-                    .map(|char| Pixel::try_from(char))
+                line.chars()
+                    .map(Pixel::try_from)
                     .collect::<Result<Vec<Pixel>>>()
-                    .context("Bad input")?;
-
-                Ok(Line { pixels })
+                    .context("Bad input")
             })
-            .collect::<Result<Vec<Line>>>()?;
+            .collect::<Result<Vec<Vec<Pixel>>>>()?;
 
-        // transpose rows to get columns
-        let cols = (0..rows[0].pixels.len())
-            .map(|col| {
-                let pixels = (0..rows.len())
-                    .map(|row| rows[row].pixels[col].clone())
-                    .collect::<Vec<Pixel>>();
+        let width = pixels.first().context("Empty image")?.len();
+        if pixels.iter().any(|row| row.len() != width) {
+            return Err(anyhow::anyhow!("Lines of different sizes"));
+        }
 
-                Line { pixels }
-            })
+        let rows = pixels
+            .iter()
+            .map(|row| Line::from_pixels(row.iter()))
+            .collect::<Vec<Line>>();
+
+        // transpose rows to get columns
+        let cols = (0..width)
+            .map(|col| Line::from_pixels(pixels.iter().map(|row| &row[col])))
             .collect::<Vec<Line>>();
 
         Ok(Self { rows, cols })
     }
 }
 
-impl Image {
-    fn is_horizontal_mirror(&self, index: usize) -> bool {
-        let compare = std::cmp::min(self.rows.len() - index, index);
+impl Line {
+    fn from_pixels<'a>(pixels: impl Iterator<Item = &'a Pixel>) -> Self {
+        let mut result = Self {
+            len: 0,
+            bits: Vec::new(),
+        };
 
-        for k in 0..compare {
-            if self.rows[index - k - 1] != self.rows[index + k] {
-                return false;
+        for pixel in pixels {
+            if result.len.is_multiple_of(64) {
+                result.bits.push(0);
             }
+            if *pixel == Pixel::On {
+                result.bits[result.len / 64] |= 1 << (result.len % 64);
+            }
+            result.len += 1;
         }
 
-        true
+        result
     }
 
-    fn is_vertical_mirror(&self, index: usize) -> bool {
-        let compare = std::cmp::min(self.cols.len() - index, index);
-
-        for k in 0..compare {
-            if self.cols[index - k - 1] != self.cols[index + k] {
-                return false;
-            }
+    fn get(&self, index: usize) -> Pixel {
+        match self.bits[index / 64] >> (index % 64) & 1 {
+            1 => Pixel::On,
+            _ => Pixel::Off,
         }
-
-        true
     }
 
-    fn find_all_mirrors(&self, stop_on_first_find: bool) -> Vec<usize> {
-        let mut result = Vec::new();
+    fn mismatches(&self, other: &Self) -> usize {
+        self.bits
+            .iter()
+            .zip(other.bits.iter())
+            .map(|(a, b)| (a ^ b).count_ones() as usize)
+            .sum()
+    }
+}
 
-        for r in 1..self.rows.len() {
-            if self.is_horizontal_mirror(r) {
-                result.push(100 * r);
-                if stop_on_first_find {
-                    break;
-                }
-            }
+// For every reflection line between two of these lines, how many pixels don't match their
+// reflection. Compares each pair of lines once, adding its mismatches to the only axis
+// that reflects one line into the other
+fn mismatches_per_axis(lines: &[Line]) -> Vec<usize> {
+    // the axis between lines i and j (i < j) sits right after their middle, so only pairs at an odd
+    // distance reflect into each other
+    let mut result = vec![0; lines.len() + 1];
+
+    for i in 0..lines.len() {
+        for j in (i + 1..lines.len()).step_by(2) {
+            result[(i + j).div_ceil(2)] += lines[i].mismatches(&lines[j]);
         }
+    }
 
-        if !(stop_on_first_find && result.len() > 0) {
-            for c in 1..self.cols.len() {
-                if self.is_vertical_mirror(c) {
-                    result.push(c);
-                }
-            }
-        }
+    result
+}
 
-        result
+impl Image {
+    // Reflection lines with exactly this many smudges: pixels that would need to be switched
+    // for the reflection to be perfect. Horizontal ones first
+    pub fn mirrors(&self, smudges: usize) -> Vec<Axis> {
+        let rows = mismatches_per_axis(&self.rows);
+        let cols = mismatches_per_axis(&self.cols);
+
+        let horizontal = (1..self.rows.len())
+            .filter(|r| rows[*r] == smudges)
+            .map(Axis::Horizontal);
+        let vertical = (1..self.cols.len())
+            .filter(|c| cols[*c] == smudges)
+            .map(Axis::Vertical);
+
+        horizontal.chain(vertical).collect()
     }
 
-    fn switch_pixel_at(&mut self, row: usize, col: usize) {
-        if let Some(row) = self.rows.get_mut(row) {
-            if let Some(cell) = row.pixels.get_mut(col) {
-                *cell = if cell == &Pixel::Off {
-                    Pixel::On
-                } else {
-                    Pixel::Off
-                };
-            }
-        }
-        if let Some(col) = self.cols.get_mut(col) {
-            if let Some(cell) = col.pixels.get_mut(row) {
-                *cell = if cell == &Pixel::Off {
-                    Pixel::On
-                } else {
-                    Pixel::Off
-                };
-            }
-        }
+    fn pixel(&self, row: usize, col: usize) -> Pixel {
+        self.rows[row].get(col)
     }
-}
 
-pub fn run_part_1(input: String) -> Result<usize> {
-    let images = input
-        .trim()
-        .split("\n\n")
-        .map(|s| Image::try_from(s))
-        .collect::<Result<Vec<Image>>>()?;
+    // Whether every pixel of the region matches the one it is sent to by the symmetry
+    fn holds_over(&self, symmetry: Symmetry, region: &Region) -> bool {
+        let (height, width) = (region.height, region.width);
+
+        (0..height).all(|i| {
+            (0..width).all(|j| {
+                let (other_i, other_j) = match symmetry {
+                    Symmetry::Diagonal => (j, i),
+                    Symmetry::AntiDiagonal => (width - 1 - j, height - 1 - i),
+                    Symmetry::Rotation90 => (width - 1 - j, i),
+                    Symmetry::Rotation180 => (height - 1 - i, width - 1 - j),
+                };
 
-    let mut result = 0;
+                self.pixel(region.row + i, region.col + j)
+                    == self.pixel(region.row + other_i, region.col + other_j)
+            })
+        })
+    }
 
-    for i in images.iter() {
-        result += i.find_all_mirrors(true)[0];
+    // Largest region the symmetry holds over, which has to be a square for all but the 180
+    // degrees rotation. Ties go to the tallest one, then the one closest to the top left corner
+    pub fn largest_symmetric_region(&self, symmetry: Symmetry) -> Region {
+        let (rows, cols) = (self.rows.len(), self.cols.len());
+
+        let mut sizes = (1..=rows)
+            .flat_map(|height| (1..=cols).map(move |width| (height, width)))
+            .filter(|(height, width)| symmetry == Symmetry::Rotation180 || height == width)
+            .collect::<Vec<(usize, usize)>>();
+        sizes.sort_by_key(|(height, width)| std::cmp::Reverse((height * width, *height)));
+
+        sizes
+            .into_iter()
+            .flat_map(|(height, width)| {
+                (0..=rows - height).flat_map(move |row| {
+                    (0..=cols - width).map(move |col| Region {
+                        row,
+                        col,
+                        height,
+                        width,
+                    })
+                })
+            })
+            .find(|region| self.holds_over(symmetry, region))
+            // a single pixel is always symmetric
+            .unwrap_or(Region {
+                row: 0,
+                col: 0,
+                height: 1,
+                width: 1,
+            })
     }
 
-    Ok(result)
+    pub fn symmetries(&self) -> Vec<(Symmetry, Region)> {
+        [
+            Symmetry::Diagonal,
+            Symmetry::AntiDiagonal,
+            Symmetry::Rotation90,
+            Symmetry::Rotation180,
+        ]
+        .into_iter()
+        .map(|symmetry| (symmetry, self.largest_symmetric_region(symmetry)))
+        .collect()
+    }
 }
 
-pub fn run_part_2(input: String) -> Result<usize> {
-    let mut images = input
+// Sum of the summaries of the first reflection line with exactly this many smudges in each image
+pub fn summarize(input: &str, smudges: usize) -> Result<usize> {
+    let images = input
         .trim()
         .split("\n\n")
-        .map(|s| Image::try_from(s))
+        .map(Image::try_from)
         .collect::<Result<Vec<Image>>>()?;
 
-    let mut result = 0;
-
-    for i in images.iter_mut() {
-        // calculating result before removing smudge for comparison
-        let old_result = i.find_all_mirrors(true)[0];
-
-        // finding smudge via brute force
-        'outer: for r in 0..i.rows.len() {
-            for c in 0..i.cols.len() {
-                i.switch_pixel_at(r, c);
-
-                let all_mirrors = i.find_all_mirrors(false);
-
-                let other_mirror = all_mirrors
-                    .iter()
-                    .find(|mi| **mi != old_result);
-
-                if let Some(new_result) = other_mirror {
-                    result += new_result;
-                    break 'outer;
-                }
+    images
+        .iter()
+        .map(|i| {
+            i.mirrors(smudges)
+                .first()
+                .map(|axis| axis.summary())
+                .context("Reflection line not found")
+        })
+        .sum()
+}
 
-                i.switch_pixel_at(r, c);
-            }
-        }
-    }
+pub fn run_part_1(input: String) -> Result<usize> {
+    summarize(&input, 0)
+}
 
-    Ok(result)
+pub fn run_part_2(input: String) -> Result<usize> {
+    summarize(&input, 1)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::d13::run_part_1;
     use crate::d13::run_part_2;
+    use crate::d13::Axis;
+    use crate::d13::Image;
+    use crate::d13::Region;
+    use crate::d13::Symmetry;
+    use crate::test_util::Lcg;
     use std::fs::read_to_string;
 
     #[test]
@@ -213,4 +299,92 @@ mod tests {
         let input = read_to_string("src/d13/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 28475);
     }
+
+    #[test]
+    fn mirrors_with_smudges() {
+        let input = read_to_string("src/d13/test.txt").expect("could not read file");
+        let images = input
+            .trim()
+            .split("\n\n")
+            .map(|s| Image::try_from(s).unwrap())
+            .collect::<Vec<Image>>();
+
+        assert_eq!(images[0].mirrors(0), [Axis::Vertical(5)]);
+        assert_eq!(images[1].mirrors(0), [Axis::Horizontal(4)]);
+        assert_eq!(images[0].mirrors(1), [Axis::Horizontal(3)]);
+        assert_eq!(images[1].mirrors(1), [Axis::Horizontal(1)]);
+
+        // every axis has some number of smudges, at most one per pair of reflected pixels
+        for image in images.iter() {
+            let axes = (0..=image.rows.len() * image.cols.len())
+                .map(|k| image.mirrors(k).len())
+                .sum::<usize>();
+            assert_eq!(axes, image.rows.len() - 1 + image.cols.len() - 1);
+        }
+    }
+
+    #[test]
+    fn wide_images() {
+        // 35 pseudo-random pixels mirrored into 70 columns, on rows that all differ
+        let mut lcg = Lcg::new(42);
+        let mut rows = Vec::new();
+        for _ in 0..5 {
+            let half = (0..35)
+                .map(|_| if lcg.next_u32() >> 31 == 1 { '#' } else { '.' })
+                .collect::<String>();
+            rows.push(format!(
+                "{}{}",
+                half,
+                half.chars().rev().collect::<String>()
+            ));
+        }
+
+        let image = Image::try_from(&rows.join("\n")[..]).unwrap();
+        assert_eq!(image.mirrors(0), [Axis::Vertical(35)]);
+
+        // a smudge past the first 64 columns
+        let flipped = if rows[2].as_bytes()[68] == b'#' {
+            "."
+        } else {
+            "#"
+        };
+        rows[2].replace_range(68..69, flipped);
+        let image = Image::try_from(&rows.join("\n")[..]).unwrap();
+        assert!(image.mirrors(0).is_empty());
+        assert_eq!(image.mirrors(1), [Axis::Vertical(35)]);
+
+        assert!(Image::try_from("#.\n#").is_err());
+        assert!(Image::try_from("").is_err());
+    }
+
+    #[test]
+    fn diagonal_and_rotational_symmetries() {
+        let image = Image::try_from("#.#...#\n.#..#..\n#.#.#..\n...#.#.\n.#.....").unwrap();
+        let region = |row, col, height, width| Region {
+            row,
+            col,
+            height,
+            width,
+        };
+
+        assert_eq!(
+            image.symmetries(),
+            [
+                (Symmetry::Diagonal, region(0, 0, 4, 4)),
+                (Symmetry::AntiDiagonal, region(0, 0, 3, 3)),
+                (Symmetry::Rotation90, region(0, 0, 3, 3)),
+                (Symmetry::Rotation180, region(2, 1, 2, 6)),
+            ]
+        );
+
+        let image = Image::try_from("#..\n...\n..#").unwrap();
+        assert_eq!(
+            image.largest_symmetric_region(Symmetry::Rotation180),
+            region(0, 0, 3, 3)
+        );
+        assert_eq!(
+            image.largest_symmetric_region(Symmetry::Rotation90),
+            region(0, 1, 2, 2)
+        );
+    }
 }
@@ -1,10 +1,21 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
 use anyhow::{Context, Result};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum Cell {
     Empty,
     CubeRock,
     RoundRock,
+    // rolls like a round rock, pushing the round rocks in front of it out of its way
+    HeavyRock,
+    // floor that stops a rock rolling onto it, and only lets it move one cell in the next tilt
+    Sticky,
+    StickyRoundRock,
+    StickyHeavyRock,
 }
 
 impl TryFrom<char> for Cell {
@@ -15,6 +26,10 @@ impl TryFrom<char> for Cell {
             '.' => Ok(Self::Empty),
             '#' => Ok(Self::CubeRock),
             'O' => Ok(Self::RoundRock),
+            '@' => Ok(Self::HeavyRock),
+            '~' => Ok(Self::Sticky),
+            'o' => Ok(Self::StickyRoundRock),
+            'a' => Ok(Self::StickyHeavyRock),
             _ => Err(anyhow::anyhow!("Invalid cell")),
         }
     }
@@ -26,12 +41,27 @@ impl Cell {
             Cell::Empty => '.',
             Cell::CubeRock => '#',
             Cell::RoundRock => 'O',
+            Cell::HeavyRock => '@',
+            Cell::Sticky => '~',
+            Cell::StickyRoundRock => 'o',
+            Cell::StickyHeavyRock => 'a',
         }
     }
+
+    fn is_rock(&self) -> bool {
+        !matches!(self, Cell::Empty | Cell::CubeRock | Cell::Sticky)
+    }
+
+    fn is_sticky(&self) -> bool {
+        matches!(
+            self,
+            Cell::Sticky | Cell::StickyRoundRock | Cell::StickyHeavyRock
+        )
+    }
 }
 
-#[derive(Debug, Clone)]
-struct Grid {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid {
     contents: Vec<Vec<Cell>>,
 }
 
@@ -49,6 +79,12 @@ impl TryFrom<&str> for Grid {
             .collect::<Result<Vec<Vec<Cell>>>>()
             .context("Invalid input")?;
 
+        if let Some(first) = contents.first() {
+            if contents.iter().any(|line| line.len() != first.len()) {
+                return Err(anyhow::anyhow!("Lines of different sizes"));
+            }
+        }
+
         Ok(Self { contents })
     }
 }
@@ -68,124 +104,243 @@ impl std::fmt::Display for Grid {
     }
 }
 
-impl Grid {
-    fn rotate_90(&mut self) {
-        let rows = self.contents.len();
-        let cols = self.contents[0].len();
-
-        let rotated = (0..cols)
-            .rev()
-            .map(|col| {
-                (0..rows)
-                    .map(|row| self.contents[row][col].clone())
-                    .collect::<Vec<Cell>>()
-            })
-            .collect::<Vec<Vec<Cell>>>();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
 
-        self.contents = rotated;
+impl TryFrom<char> for Direction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: char) -> Result<Self> {
+        match value {
+            'N' => Ok(Self::North),
+            'W' => Ok(Self::West),
+            'S' => Ok(Self::South),
+            'E' => Ok(Self::East),
+            _ => Err(anyhow::anyhow!("Invalid direction")),
+        }
     }
+}
 
-    fn rotate_270(&mut self) {
-        let rows = self.contents.len();
-        let cols = self.contents[0].len();
+pub const SPIN_CYCLE: [Direction; 4] = [
+    Direction::North,
+    Direction::West,
+    Direction::South,
+    Direction::East,
+];
 
-        let rotated = (0..cols)
-            .map(|col| {
-                (0..rows)
-                    .rev()
-                    .map(|row| self.contents[row][col].clone())
-                    .collect::<Vec<Cell>>()
-            })
-            .collect::<Vec<Vec<Cell>>>();
+// Tilt sequence such as "NNESW"
+pub fn parse_sequence(value: &str) -> Result<Vec<Direction>> {
+    value.chars().map(Direction::try_from).collect()
+}
+
+impl Grid {
+    fn size(&self) -> (usize, usize) {
+        (
+            self.contents.len(),
+            self.contents.first().map_or(0, |line| line.len()),
+        )
+    }
+
+    // Position of the k-th cell of a line, counting from the edge the rocks roll toward.
+    // Lines are columns when tilting north or south, and rows otherwise
+    fn position(&self, direction: Direction, line: usize, k: usize) -> (usize, usize) {
+        let (rows, cols) = self.size();
+
+        match direction {
+            Direction::North => (k, line),
+            Direction::South => (rows - 1 - k, line),
+            Direction::West => (line, k),
+            Direction::East => (line, cols - 1 - k),
+        }
+    }
+
+    fn set(&mut self, direction: Direction, line: usize, k: usize, cell: Cell) {
+        let (row, col) = self.position(direction, line, k);
+        self.contents[row][col] = cell;
+    }
 
-        self.contents = rotated;
+    // Puts a rock on a cell, keeping its floor
+    fn place(&mut self, direction: Direction, line: usize, k: usize, rock: Cell) {
+        let (row, col) = self.position(direction, line, k);
+        self.contents[row][col] = match (self.contents[row][col].is_sticky(), rock) {
+            (true, Cell::HeavyRock) => Cell::StickyHeavyRock,
+            (true, _) => Cell::StickyRoundRock,
+            (false, rock) => rock,
+        };
     }
 
-    fn roll_west(&mut self) {
-        for line in self.contents.iter_mut() {
-            let mut j = 1;
-            loop {
-                match line.get(j) {
-                    Some(&Cell::RoundRock) => {
-                        if let Some(&Cell::Empty) = line.get(j.checked_sub(1).unwrap_or(0)) {
-                            line[j - 1] = Cell::RoundRock;
-                            line[j] = Cell::Empty;
-                            j -= 1;
+    // Sweeps each line from the edge the rocks roll toward, with a cursor on the first cell a
+    // rock can still roll to. The rocks piled since the last obstacle start with the heavy ones,
+    // and a rock stops on the first sticky cell in its path. The sticky floor stays under it,
+    // and in the next tilt the rock moves one cell at most before stopping
+    pub fn tilt(&mut self, direction: Direction) {
+        let (rows, cols) = self.size();
+        let (lines, length) = match direction {
+            Direction::North | Direction::South => (cols, rows),
+            Direction::West | Direction::East => (rows, cols),
+        };
+
+        for line in 0..lines {
+            let mut cursor = 0;
+            let mut start_of_pile = 0;
+            let mut heavy_rocks = 0;
+            let mut sticky: Option<usize> = None;
+
+            for k in 0..length {
+                let (row, col) = self.position(direction, line, k);
+                let cell = self.contents[row][col].clone();
+
+                match cell {
+                    Cell::CubeRock => {
+                        cursor = k + 1;
+                        start_of_pile = cursor;
+                        heavy_rocks = 0;
+                    }
+                    Cell::Sticky => sticky = Some(k),
+                    Cell::StickyRoundRock | Cell::StickyHeavyRock => {
+                        // moves to the next cell if it is free, leaving the sticky floor behind
+                        // for the rocks rolling after it
+                        if k > cursor {
+                            let rock = match cell {
+                                Cell::StickyHeavyRock => Cell::HeavyRock,
+                                _ => Cell::RoundRock,
+                            };
+                            self.contents[row][col] = Cell::Sticky;
+                            self.place(direction, line, k - 1, rock);
+                            sticky = Some(k);
+                            cursor = k;
                         } else {
-                            j += 1;
+                            cursor = k + 1;
+                        }
+                        start_of_pile = cursor;
+                        heavy_rocks = 0;
+                    }
+                    Cell::Empty => {}
+                    Cell::RoundRock | Cell::HeavyRock => {
+                        self.contents[row][col] = Cell::Empty;
+
+                        match sticky.filter(|s| *s >= cursor) {
+                            Some(s) => {
+                                self.place(direction, line, s, cell);
+                                cursor = s + 1;
+                                start_of_pile = cursor;
+                                heavy_rocks = 0;
+                            }
+                            None if cell == Cell::HeavyRock => {
+                                // takes the place of the first round rock of the pile, if any,
+                                // which goes to the back of it
+                                let to = start_of_pile + heavy_rocks;
+                                if to < cursor {
+                                    self.set(direction, line, cursor, Cell::RoundRock);
+                                }
+                                self.set(direction, line, to, Cell::HeavyRock);
+                                heavy_rocks += 1;
+                                cursor += 1;
+                            }
+                            None => {
+                                self.set(direction, line, cursor, Cell::RoundRock);
+                                cursor += 1;
+                            }
                         }
                     }
-                    Some(_) => j += 1,
-                    None => break,
                 }
             }
         }
     }
 
-    fn calculate_load(&self) -> Vec<usize> {
-        let mut result = Vec::new();
+    pub fn spin(&mut self, sequence: &[Direction]) {
+        for direction in sequence {
+            self.tilt(*direction);
+        }
+    }
 
-        for line in self.contents.iter() {
-            let mut line_result = 0;
-            for j in 0..line.len() {
-                if let Some(&Cell::RoundRock) = line.get(j) {
-                    line_result += line.len() - j;
-                }
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn after_spin_cycles(&self, n: u64) -> Grid {
+        self.after_spins(&SPIN_CYCLE, n)
+    }
+
+    // The grid after spinning n times with this tilt sequence. The grids seen so far are looked
+    // up by their hash, and compared cell by cell on a match, so that the first repeated grid
+    // gives the cycle
+    pub fn after_spins(&self, sequence: &[Direction], n: u64) -> Grid {
+        let mut grid = self.clone();
+        let mut history: Vec<Grid> = Vec::new();
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+
+        loop {
+            let i = history.len();
+            if i as u64 == n {
+                return grid;
+            }
+
+            let hash = grid.state_hash();
+            let repeated = seen
+                .get(&hash)
+                .and_then(|candidates| candidates.iter().find(|c| history[**c] == grid));
+
+            if let Some(&start_of_cycle) = repeated {
+                let length_of_cycle = (i - start_of_cycle) as u64;
+                let offset = (n - start_of_cycle as u64) % length_of_cycle;
+                return history[start_of_cycle + offset as usize].clone();
             }
-            result.push(line_result);
+
+            seen.entry(hash).or_default().push(i);
+            history.push(grid.clone());
+            grid.spin(sequence);
         }
+    }
 
-        result
+    // Each rock weighs as many lines as there are from it to the opposite edge, included
+    pub fn load(&self, direction: Direction) -> usize {
+        let (rows, cols) = self.size();
+        let (lines, length) = match direction {
+            Direction::North | Direction::South => (cols, rows),
+            Direction::West | Direction::East => (rows, cols),
+        };
+
+        (0..lines)
+            .flat_map(|line| (0..length).map(move |k| (line, k)))
+            .filter(|(line, k)| {
+                let (row, col) = self.position(direction, *line, *k);
+                self.contents[row][col].is_rock()
+            })
+            .map(|(_, k)| length - k)
+            .sum()
     }
 }
 
 pub fn run_part_1(input: String) -> Result<usize> {
     let mut grid = Grid::try_from(input.trim())?;
 
-    grid.rotate_90();
-    grid.roll_west();
-    let result = grid.calculate_load();
+    grid.tilt(Direction::North);
 
-    Ok(result.iter().sum())
+    Ok(grid.load(Direction::North))
 }
 
 pub fn run_part_2(input: String) -> Result<usize> {
-    let mut grid = Grid::try_from(input.trim())?;
-
-    let mut history: Vec<Vec<usize>> = Vec::new();
-
-    let start_of_cycle;
-    let length_of_cycle;
-
-    grid.rotate_90();
-    loop {
-        for _ in 0..4 {
-            grid.roll_west();
-            grid.rotate_270();
-        }
-
-        let result = grid.calculate_load();
-
-        if let Some(pos) = history.iter().position(|h| *h == result) {
-            println!("State of {pos} is equal to state of {}", history.len());
-            start_of_cycle = pos;
-            length_of_cycle = history.len() - pos;
-            break;
-        } else {
-            history.push(result);
-        }
-    }
-
-    let result =
-        &history[start_of_cycle - 1 + ((1_000_000_000 - start_of_cycle) % length_of_cycle)];
+    let grid = Grid::try_from(input.trim())?;
 
-    Ok(result.iter().sum())
+    Ok(grid.after_spin_cycles(1_000_000_000).load(Direction::North))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::d14::parse_sequence;
     use crate::d14::run_part_1;
     use crate::d14::run_part_2;
+    use crate::d14::Direction;
+    use crate::d14::Grid;
+    use crate::d14::SPIN_CYCLE;
     use std::fs::read_to_string;
 
     #[test]
@@ -211,4 +366,120 @@ mod tests {
         let input = read_to_string("src/d14/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 89845);
     }
+
+    #[test]
+    fn any_number_of_spin_cycles() {
+        let input = read_to_string("src/d14/test.txt").expect("could not read file");
+        let grid = Grid::try_from(input.trim()).unwrap();
+
+        assert_eq!(grid.after_spin_cycles(0), grid);
+        assert_eq!(
+            grid.after_spin_cycles(1).to_string(),
+            ".....#....\n....#...O#\n...OO##...\n.OO#......\n.....OOO#.\n.O#...O#.#\n....O#....\n......OOOO\n#...O###..\n#..OO#....\n"
+        );
+
+        let mut spun = grid.clone();
+        for n in 0..40 {
+            assert_eq!(grid.after_spin_cycles(n), spun);
+            spun.spin(&SPIN_CYCLE);
+        }
+
+        assert_eq!(
+            grid.after_spin_cycles(1_000_000_000).load(Direction::North),
+            64
+        );
+        // the example repeats every 7 spin cycles
+        assert_eq!(
+            grid.after_spin_cycles(u64::MAX),
+            grid.after_spin_cycles(u64::MAX - 7 * 1_000_000)
+        );
+    }
+
+    #[test]
+    fn tilt_in_place() {
+        let mut grid = Grid::try_from("O.#.O\n.O..O\n#..O.\n..O.#").unwrap();
+
+        grid.tilt(Direction::North);
+        assert_eq!(grid.to_string(), "OO#OO\n..O.O\n#....\n....#\n");
+        grid.tilt(Direction::East);
+        assert_eq!(grid.to_string(), "OO#OO\n...OO\n#....\n....#\n");
+        grid.tilt(Direction::South);
+        assert_eq!(grid.to_string(), "..#..\nO...O\n#..OO\n.O.O#\n");
+        grid.tilt(Direction::West);
+        assert_eq!(grid.to_string(), "..#..\nOO...\n#OO..\nOO..#\n");
+
+        assert!(Grid::try_from("O..\n.").is_err());
+
+        // a large grid of round rocks, with a cube rock every 7 cells
+        let large = (0..1000)
+            .map(|i| {
+                (0..1000)
+                    .map(|j| match (i * 1000 + j) % 7 {
+                        0 => '#',
+                        1 | 4 => 'O',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let grid = Grid::try_from(&large[..]).unwrap();
+        let spun = grid.after_spin_cycles(3);
+        assert_eq!(
+            spun.to_string().matches('O').count(),
+            large.matches('O').count()
+        );
+        assert_eq!(
+            spun.to_string().matches('#').count(),
+            large.matches('#').count()
+        );
+    }
+
+    #[test]
+    fn custom_spins_and_rocks() {
+        let tilted = |input: &str, sequence: &str| {
+            let mut grid = Grid::try_from(input).unwrap();
+            grid.spin(&parse_sequence(sequence).unwrap());
+            grid.to_string()
+        };
+
+        // heavy rocks push round rocks out of their way
+        assert_eq!(tilted("O.@", "W"), "@O.\n");
+        assert_eq!(tilted("O#.O@O@", "W"), "O#@@OO.\n");
+        assert_eq!(tilted("@O.O", "E"), ".OO@\n");
+
+        // sticky floor stops rocks, which then block the ones behind them
+        assert_eq!(tilted(".~.O", "W"), ".o..\n");
+        assert_eq!(tilted("..~O", "W"), "..o.\n");
+        assert_eq!(tilted(".~OO", "W"), ".oO.\n");
+        assert_eq!(tilted(".~~O", "W"), ".~o.\n");
+        assert_eq!(tilted("~.@", "EW"), "a..\n");
+        assert_eq!(tilted(".~@", "W"), ".a.\n");
+
+        // a rock on sticky floor moves one cell at most, and the floor stays behind it
+        assert_eq!(tilted("o.O", "W"), "oO.\n");
+        assert_eq!(tilted("a...", "E"), "~@..\n");
+        assert_eq!(tilted("a...", "EE"), "~..@\n");
+        assert_eq!(tilted("~OO", "WE"), "~OO\n");
+        assert_eq!(tilted("~@O", "WE"), "~@O\n");
+        assert_eq!(tilted("O~.o", "W"), "O~O~\n");
+        assert_eq!(tilted("..~o", "W"), "..o~\n");
+
+        assert!(parse_sequence("NX").is_err());
+
+        let input = read_to_string("src/d14/test.txt").expect("could not read file");
+        let grid = Grid::try_from(input.trim()).unwrap();
+        let sequence = parse_sequence("NNESW").unwrap();
+        let mut spun = grid.clone();
+        for n in 0..30 {
+            assert_eq!(grid.after_spins(&sequence, n), spun);
+            spun.spin(&sequence);
+        }
+
+        let grid = Grid::try_from("O..\n.@.\n...").unwrap();
+        assert_eq!(grid.load(Direction::North), 5);
+        assert_eq!(grid.load(Direction::South), 3);
+        assert_eq!(grid.load(Direction::West), 5);
+        assert_eq!(grid.load(Direction::East), 3);
+    }
 }
@@ -1,17 +1,123 @@
+use std::fmt::Debug;
+
 use anyhow::{Context, Result};
 
+// Holiday ASCII String Helper algorithm
+pub fn hash(value: impl AsRef<[u8]>) -> usize {
+    value
+        .as_ref()
+        .iter()
+        .fold(0, |acc, c| (acc + *c as usize) * 17 % 256)
+}
+
+// Hash map with a box per hash value, where each box keeps its entries in insertion order
+#[derive(Clone)]
+pub struct LensMap<K, V, H = fn(&K) -> usize> {
+    boxes: Vec<Vec<(K, V)>>,
+    hasher: H,
+}
+
+// The hasher is left out, as closures can't be printed
+impl<K: Debug, V: Debug, H> Debug for LensMap<K, V, H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LensMap")
+            .field("boxes", &self.boxes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: AsRef<[u8]> + Eq, V> LensMap<K, V> {
+    // 256 boxes, using the HASH algorithm
+    pub fn new() -> Self {
+        Self::with_hasher(256, |key: &K| hash(key))
+    }
+}
+
+impl<K: AsRef<[u8]> + Eq, V> Default for LensMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq, V, H: Fn(&K) -> usize> LensMap<K, V, H> {
+    // The hash of a key is wrapped around the number of boxes
+    pub fn with_hasher(boxes: usize, hasher: H) -> Self {
+        Self {
+            boxes: (0..boxes.max(1)).map(|_| Vec::new()).collect(),
+            hasher,
+        }
+    }
+
+    fn box_of(&self, key: &K) -> usize {
+        (self.hasher)(key) % self.boxes.len()
+    }
+
+    // Replaces the value in place if the key is already there, or adds it at the back of its box
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let b = self.box_of(&key);
+
+        match self.boxes[b].iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => Some(std::mem::replace(v, value)),
+            None => {
+                self.boxes[b].push((key, value));
+                None
+            }
+        }
+    }
+
+    // The entries behind the removed one move forward in its box
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let b = self.box_of(key);
+        let position = self.boxes[b].iter().position(|(k, _)| k == key)?;
+
+        Some(self.boxes[b].remove(position).1)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.boxes[self.box_of(key)]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn len(&self) -> usize {
+        self.boxes.iter().map(|b| b.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.iter().all(|b| b.is_empty())
+    }
+
+    // Entries box by box, in insertion order within each box
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.boxes.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    // Folds the entries with their weight: the box number times the slot in the box, both
+    // counting from 1, as in the focusing power of the lenses
+    pub fn weighted_fold<T>(&self, init: T, mut f: impl FnMut(T, usize, &K, &V) -> T) -> T {
+        let mut result = init;
+
+        for (bn, b) in self.boxes.iter().enumerate() {
+            for (ln, (k, v)) in b.iter().enumerate() {
+                result = f(result, (bn + 1) * (ln + 1), k, v);
+            }
+        }
+
+        result
+    }
+}
+
 #[derive(Clone)]
 struct EqualInstruction {
     label: String,
     focus: usize,
-    label_hash: usize,
     raw_hash: usize,
 }
 
 #[derive(Clone)]
 struct DashInstruction {
     label: String,
-    label_hash: usize,
     raw_hash: usize,
 }
 
@@ -21,77 +127,26 @@ enum Instruction {
     Dash(DashInstruction),
 }
 
-enum InstructionType {
-    Equal,
-    Dash,
-}
-
 impl TryFrom<&str> for Instruction {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self> {
-        let mut instruction_type = InstructionType::Equal;
-
-        let (label, focus_str) = value
-            .split_once("=")
-            .or_else(|| {
-                instruction_type = InstructionType::Dash;
-                value.split_once("-")
-            })
-            .context("bad")?;
+        let raw_hash = hash(value);
 
-        let label = label.to_string();
-
-        let mut focus = 0;
-        if focus_str != "" {
-            focus = focus_str.parse::<usize>()?;
-        }
-
-        let mut label_hash = 0;
-        let mut raw_hash = 0;
-
-        value.chars().map(|c| c as usize).for_each(|n| {
-            if n == 45 {
-                instruction_type = InstructionType::Dash;
-                label_hash = raw_hash;
-            } else if n == 61 {
-                instruction_type = InstructionType::Equal;
-                label_hash = raw_hash;
-            }
-
-            raw_hash += n;
-            raw_hash = 17 * raw_hash;
-            raw_hash = raw_hash % 256;
-        });
-
-        match instruction_type {
-            InstructionType::Equal => Ok(Self::Equal(EqualInstruction {
-                label,
-                focus,
-                label_hash,
+        if let Some((label, focus)) = value.split_once("=") {
+            return Ok(Self::Equal(EqualInstruction {
+                label: label.to_string(),
+                focus: focus.parse::<usize>()?,
                 raw_hash,
-            })),
-            InstructionType::Dash => Ok(Self::Dash(DashInstruction {
-                label,
-                label_hash,
-                raw_hash,
-            })),
+            }));
         }
-    }
-}
 
-#[derive(Debug)]
-struct Lens {
-    label: String,
-    focus: usize,
-}
+        let (label, _) = value.split_once("-").context("bad")?;
 
-impl From<&EqualInstruction> for Lens {
-    fn from(value: &EqualInstruction) -> Self {
-        Self {
-            label: value.label.clone(),
-            focus: value.focus,
-        }
+        Ok(Self::Dash(DashInstruction {
+            label: label.to_string(),
+            raw_hash,
+        }))
     }
 }
 
@@ -99,7 +154,7 @@ pub fn run_part_1(input: String) -> Result<usize> {
     let raw_hashes = input
         .trim()
         .split(',')
-        .map(|i| Instruction::try_from(i))
+        .map(Instruction::try_from)
         .map(|h| match h {
             Ok(Instruction::Equal(e)) => Ok(e.raw_hash),
             Ok(Instruction::Dash(d)) => Ok(d.raw_hash),
@@ -111,50 +166,34 @@ pub fn run_part_1(input: String) -> Result<usize> {
 }
 
 pub fn run_part_2(input: String) -> Result<usize> {
-    let mut boxes: Vec<Vec<Lens>> = Vec::new();
-    for _ in 0..256 {
-        boxes.push(Vec::new());
-    }
+    let mut boxes: LensMap<String, usize> = LensMap::new();
 
     let instructions = input
         .trim()
         .split(',')
-        .map(|i| Instruction::try_from(i))
+        .map(Instruction::try_from)
         .collect::<Result<Vec<Instruction>>>()?;
 
-    for i in instructions.iter() {
+    for i in instructions.into_iter() {
         match i {
             Instruction::Equal(e) => {
-                match boxes[e.label_hash].iter_mut().find(|l| e.label == l.label) {
-                    Some(l) => {
-                        l.focus = e.focus;
-                    }
-                    None => {
-                        boxes[e.label_hash].push(Lens::from(e));
-                    }
-                }
+                boxes.insert(e.label, e.focus);
             }
             Instruction::Dash(d) => {
-                boxes[d.label_hash].retain(|l| l.label != d.label);
+                boxes.remove(&d.label);
             }
         }
     }
 
-    let mut result = 0;
-
-    for (bn, b) in boxes.iter().enumerate() {
-        for (ln, l) in b.iter().enumerate() {
-            result += (bn + 1) * (ln + 1) * l.focus
-        }
-    }
-
-    Ok(result)
+    Ok(boxes.weighted_fold(0, |acc, weight, _, focus| acc + weight * focus))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::d15::hash;
     use crate::d15::run_part_1;
     use crate::d15::run_part_2;
+    use crate::d15::LensMap;
     use std::fs::read_to_string;
 
     #[test]
@@ -180,4 +219,54 @@ mod tests {
         let input = read_to_string("src/d15/prod.txt").expect("could not read file");
         assert_eq!(run_part_2(input).unwrap(), 269410);
     }
+
+    #[test]
+    fn lens_map() {
+        assert_eq!(hash("HASH"), 52);
+
+        let mut map: LensMap<&str, usize> = LensMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert("rn", 1), None);
+        assert_eq!(map.insert("cm", 2), None);
+        assert_eq!(map.insert("qp", 3), None);
+        assert_eq!(map.insert("rn", 4), Some(1));
+        assert_eq!(map.get(&"rn"), Some(&4));
+        assert_eq!(map.get(&"ot"), None);
+        assert_eq!(
+            map.iter().collect::<Vec<(&&str, &usize)>>(),
+            [(&"rn", &4), (&"cm", &2), (&"qp", &3)]
+        );
+        assert_eq!(map.remove(&"rn"), Some(4));
+        assert_eq!(map.remove(&"rn"), None);
+        assert_eq!(map.len(), 2);
+        // cm is now first in box 0, qp first in box 1
+        assert_eq!(map.weighted_fold(0, |acc, w, _, v| acc + w * v), 2 + 2 * 3);
+
+        // pluggable hash: by remainder, with 3 boxes
+        let mut map = LensMap::with_hasher(3, |key: &u32| *key as usize);
+        for key in [4, 7, 5, 1, 3] {
+            map.insert(key, key * 10);
+        }
+        map.remove(&7);
+        assert_eq!(
+            map.iter().map(|(k, _)| *k).collect::<Vec<u32>>(),
+            [3, 4, 1, 5]
+        );
+        assert_eq!(
+            map.weighted_fold(Vec::new(), |mut acc, w, k, _| {
+                acc.push((w, *k));
+                acc
+            }),
+            [(1, 3), (2, 4), (4, 1), (3, 5)]
+        );
+
+        let copy = map.clone();
+        map.insert(9, 90);
+        assert_eq!(copy.len(), 4);
+        assert_eq!(copy.get(&9), None);
+        assert_eq!(
+            format!("{:?}", copy),
+            "LensMap { boxes: [[(3, 30)], [(4, 40), (1, 10)], [(5, 50)]], .. }"
+        );
+    }
 }
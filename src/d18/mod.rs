@@ -1,5 +1,7 @@
 use anyhow::{Context, Result};
 
+use crate::polygon;
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct Coordinate {
     x: usize,
@@ -146,7 +148,6 @@ impl std::fmt::Debug for Map {
 impl Map {
     fn from_plan(plan: &Plan) -> Self {
         let mut vertices = Vec::new();
-        let mut perimeter = 0;
 
         let mut current_position = Coordinate {
             x: usize::MAX / 2,
@@ -158,7 +159,6 @@ impl Map {
         for ins in plan.instructions.iter() {
             for _ in 0..ins.steps {
                 current_position = current_position.single_step(&ins.direction);
-                perimeter += 1;
             }
             vertices.push(current_position.clone());
         }
@@ -179,26 +179,12 @@ impl Map {
         let max_y = vertices.iter().max_by(|a, b| a.y.cmp(&b.y)).unwrap().y;
         let size = Coordinate { x: max_x, y: max_y };
 
-        // shoelace formula to find the area
-        let mut area = 0.;
-        for k in 0..vertices.len() - 1 {
-            let x1 = vertices[k].x as f64;
-            let y1 = vertices[k].y as f64;
-            let x2 = vertices[k + 1].x as f64;
-            let y2 = vertices[k + 1].y as f64;
-            area += x1 * y2 - x2 * y1;
-        }
-        area = (area / 2.).abs();
-        dbg!(&area, &perimeter);
-
-        // the shoelace formula is calculating the area from the center of each tile, but the area
-        // should cover the entire tile, so we need to add 0.5m2 per tile in the perimeter that per
-        // tile in the perimeter
-        area += perimeter as f64 / 2.;
-
-        // there is also the need to add 1 m2 due to cover for the 360 degress of uncovered area
-        // that adds to all vertices of a polygon
-        area += 1.;
+        // shoelace formula and Pick's theorem to find the tiles inside and on the perimeter
+        let corners = vertices
+            .iter()
+            .map(|v| (v.x as i64, v.y as i64))
+            .collect::<Vec<(i64, i64)>>();
+        let area = polygon::covered_tiles(&corners) as f64;
 
         Self {
             vertices,
@@ -20,3 +20,6 @@ pub mod d19;
 pub mod d20;
 pub mod d21;
 pub mod d23;
+pub mod polygon;
+#[cfg(test)]
+mod test_util;
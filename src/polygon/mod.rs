@@ -0,0 +1,43 @@
+// Shared engine for the area of polygons drawn on a grid, whose vertices sit at the center
+// of grid tiles. Vertices are (row, column) pairs, listed in order around the polygon, with
+// or without the first one repeated at the end.
+
+fn greatest_common_divisor(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        return a.abs();
+    }
+    greatest_common_divisor(b, a % b)
+}
+
+fn edges(vertices: &[(i64, i64)]) -> impl Iterator<Item = ((i128, i128), (i128, i128))> + '_ {
+    let to_wide = |(a, b): (i64, i64)| (a as i128, b as i128);
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(move |(a, b)| (to_wide(*a), to_wide(*b)))
+}
+
+// shoelace formula, twice the area so that it is always an integer
+pub fn double_area(vertices: &[(i64, i64)]) -> i128 {
+    edges(vertices)
+        .map(|((x1, y1), (x2, y2))| x1 * y2 - x2 * y1)
+        .sum::<i128>()
+        .abs()
+}
+
+// tiles crossed by the edges of the polygon
+pub fn boundary_tiles(vertices: &[(i64, i64)]) -> i128 {
+    edges(vertices)
+        .map(|((x1, y1), (x2, y2))| greatest_common_divisor(x2 - x1, y2 - y1))
+        .sum()
+}
+
+// Pick's theorem, area = interior + boundary / 2 - 1, gives the tiles strictly inside
+pub fn interior_tiles(vertices: &[(i64, i64)]) -> i128 {
+    ((double_area(vertices) - boundary_tiles(vertices) + 2) / 2).max(0)
+}
+
+// tiles inside the polygon or on its boundary
+pub fn covered_tiles(vertices: &[(i64, i64)]) -> i128 {
+    interior_tiles(vertices) + boundary_tiles(vertices)
+}